@@ -1,7 +1,27 @@
+use corelib::fs::Filter;
+use corelib::store::LogStore;
 use proto::towl::{Entry, GetRequest};
 
 #[tokio::main]
 async fn main() -> Result<(), String> {
+  // Read a local data directory without a server,
+  // e.g. `cli data web-1` for the entries of one sender
+  let mut args = std::env::args().skip(1);
+  if let Some(dir) = args.next() {
+    let mut filter = Filter::new();
+    for sender in args {
+      filter = filter.sender(&sender);
+    }
+    let store = LogStore::open_readonly(dir).map_err(|e| e.to_string())?;
+    let mut count = 0;
+    for entry in store.query(filter) {
+      println!("{:?}", entry.map_err(|e| e.to_string())?);
+      count += 1;
+    }
+    println!("Result count is {count}");
+    return Ok(());
+  }
+
   let mut client = proto::towl::towl_server_client::TowlServerClient::connect("http://[::1]:50011")
     .await
     .unwrap();
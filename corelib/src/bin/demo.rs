@@ -1,12 +1,10 @@
-use std::{
-  io::{Cursor, Write},
-  path::Path,
-  sync::{Arc, Mutex},
-};
+use std::sync::{Arc, Mutex};
 
 use chrono::Utc;
-use corelib::fs::{Entry, LogFile};
-use tokio::{spawn, task::spawn_blocking};
+use corelib::fs::{Durability, Entry, LogFile};
+use tokio::task::spawn_blocking;
+
+const BATCH_SIZE: usize = 1_000;
 
 #[tokio::main]
 async fn main() {
@@ -48,7 +46,7 @@ async fn read(count: i32, log: Arc<Mutex<LogFile>>) {
 
   // Spawn background task to run filter
   let h = spawn_blocking(move || {
-    let mut log = log.lock().unwrap();
+    let log = log.lock().unwrap();
     log
       .stream(Utc::now() - chrono::Duration::days(1), tx)
       .unwrap();
@@ -74,14 +72,20 @@ async fn write(count: i32, log: Arc<Mutex<LogFile>>) {
 
   spawn_blocking(move || {
     let mut log = log.lock().unwrap();
-    for i in 0..count {
-      let entry = Entry {
+    // Group commit: sync once per batch
+    log.set_durability(Durability::Always);
+    let entries: Vec<Entry> = (0..count)
+      .map(|i| Entry {
         sender: i.to_string(),
         received: Utc::now(),
         log_format: 0,
         log_entry: format!("demodemodemo{}", i),
-      };
-      log.add_entry(entry).unwrap();
+        fields: None,
+        seq: 0,
+      })
+      .collect();
+    for batch in entries.chunks(BATCH_SIZE) {
+      log.add_entries(batch).unwrap();
     }
   })
   .await
@@ -0,0 +1,148 @@
+use std::fmt;
+use std::path::PathBuf;
+
+/// Errors returned by corelib operations
+#[derive(Debug)]
+pub enum Error {
+  /// Underlying file system error
+  Io(std::io::Error),
+  /// Stored data cannot be deserialized
+  Decode(bincode::Error),
+  /// Data cannot be serialized
+  Encode(bincode::Error),
+  /// Compressed block cannot be decompressed
+  Decompress(String),
+  /// File does not start with the towl magic number
+  BadMagic,
+  /// File was written with a towl version we cannot read
+  UnsupportedVersion(i32),
+  /// File of an older towl version opened for writing, see `fs::migrate`
+  NeedsMigrate(i32),
+  /// Target file already exists
+  AlreadyExists(PathBuf),
+  /// Serialized data does not fit into its fixed size region
+  RegionOverflow {
+    region: &'static str,
+    size: u64,
+    capacity: u64,
+  },
+  /// Entry does not fit into a single record
+  EntryTooLarge { size: u64, max: u64 },
+  /// Invalid data found at the given byte offset
+  Corrupt { offset: u64 },
+  /// Write attempted through a read-only handle
+  ReadOnly,
+  /// File is locked by another writer or process
+  Locked(PathBuf),
+  /// No key with the id of an encrypted file
+  MissingKey(String),
+  /// Key does not match the one the file was encrypted with
+  WrongKey(String),
+  /// Encrypted record failed authentication
+  Decrypt,
+  /// Invalid line in a key file
+  KeyFile { line: usize },
+  /// Hash chain verification of a file without hash chain
+  NoHashChain,
+  /// No file with this id in the store catalog
+  UnknownId(usize),
+  /// Operation needs a sealed file, but it is still written
+  NotSealed(usize),
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Error::Io(e) => write!(f, "I/O error: {e}"),
+      Error::Decode(e) => write!(f, "Decode error: {e}"),
+      Error::Encode(e) => write!(f, "Encode error: {e}"),
+      Error::Decompress(e) => write!(f, "Decompress error: {e}"),
+      Error::BadMagic => write!(f, "Not a towl log file. Magic error."),
+      Error::UnsupportedVersion(v) => write!(f, "Unsupported towl version: {v}"),
+      Error::NeedsMigrate(v) => write!(f, "Towl version {v} file must be migrated before writing"),
+      Error::AlreadyExists(path) => {
+        write!(f, "Log file have already exist: {}", path.display())
+      }
+      Error::RegionOverflow {
+        region,
+        size,
+        capacity,
+      } => write!(
+        f,
+        "Serialized {region} is {size} bytes, but its region is only {capacity} bytes"
+      ),
+      Error::EntryTooLarge { size, max } => {
+        write!(
+          f,
+          "Serialized entry is {size} bytes, but at most {max} bytes are stored"
+        )
+      }
+      Error::Corrupt { offset } => write!(f, "Corrupt data at byte offset {offset}"),
+      Error::ReadOnly => write!(f, "Log file is opened read-only"),
+      Error::Locked(path) => write!(f, "Log file is locked: {}", path.display()),
+      Error::MissingKey(id) => write!(f, "No encryption key found with id {id}"),
+      Error::WrongKey(id) => write!(f, "Wrong encryption key for key id {id}"),
+      Error::Decrypt => write!(f, "Cannot decrypt record, data was modified"),
+      Error::KeyFile { line } => write!(f, "Invalid key file entry in line {line}"),
+      Error::NoHashChain => write!(f, "Log file has no hash chain"),
+      Error::UnknownId(id) => write!(f, "No log file with id {id}"),
+      Error::NotSealed(id) => write!(f, "Log file {id} is not sealed"),
+    }
+  }
+}
+
+impl std::error::Error for Error {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      Error::Io(e) => Some(e),
+      Error::Decode(e) | Error::Encode(e) => Some(e),
+      _ => None,
+    }
+  }
+}
+
+impl From<std::io::Error> for Error {
+  fn from(e: std::io::Error) -> Self {
+    Error::Io(e)
+  }
+}
+
+impl From<bincode::Error> for Error {
+  fn from(e: bincode::Error) -> Self {
+    match *e {
+      // Keep I/O failures distinguishable from corrupt data. Running
+      // out of input is not one: it is all an in-memory decode can
+      // report, and means truncated data when reading a file.
+      bincode::ErrorKind::Io(io) if io.kind() != std::io::ErrorKind::UnexpectedEof => Error::Io(io),
+      kind => Error::Decode(Box::new(kind)),
+    }
+  }
+}
+
+#[cfg(feature = "tonic")]
+impl From<Error> for tonic::Status {
+  fn from(e: Error) -> Self {
+    let msg = e.to_string();
+    match e {
+      Error::Io(ref io) if io.kind() == std::io::ErrorKind::NotFound => {
+        tonic::Status::not_found(msg)
+      }
+      Error::UnknownId(_) => tonic::Status::not_found(msg),
+      Error::AlreadyExists(_) => tonic::Status::already_exists(msg),
+      Error::EntryTooLarge { .. } => tonic::Status::invalid_argument(msg),
+      Error::BadMagic
+      | Error::UnsupportedVersion(_)
+      | Error::NeedsMigrate(_)
+      | Error::ReadOnly
+      | Error::NoHashChain
+      | Error::NotSealed(_) => tonic::Status::failed_precondition(msg),
+      Error::MissingKey(_) | Error::WrongKey(_) => tonic::Status::permission_denied(msg),
+      Error::Decode(_) | Error::Decompress(_) | Error::Decrypt | Error::Corrupt { .. } => {
+        tonic::Status::data_loss(msg)
+      }
+      Error::RegionOverflow { .. } => tonic::Status::out_of_range(msg),
+      Error::Locked(_) => tonic::Status::unavailable(msg),
+      Error::Io(_) | Error::Encode(_) | Error::KeyFile { .. } => tonic::Status::internal(msg),
+    }
+  }
+}
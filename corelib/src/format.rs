@@ -0,0 +1,163 @@
+//! Log formats and parsers
+//!
+//! `Entry.log_format` stores a numeric format code. Known codes are
+//! listed by `LogFormat`; a `Registry` maps codes to parsers that turn
+//! the raw `log_entry` into structured `Fields`. Formats are added by
+//! registering a parser for a new code, the storage layer only keeps
+//! the code and the parsed fields.
+use crate::fs::{Entry, Fields, Severity};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+mod json;
+mod logfmt;
+mod syslog;
+mod text;
+
+pub use json::{JournaldParser, JsonParser};
+pub use logfmt::LogfmtParser;
+pub use syslog::SyslogParser;
+pub use text::TextParser;
+
+/// Built-in `log_format` codes
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LogFormat {
+  /// Free text
+  Text,
+  /// journalctl JSON output
+  Journald,
+  /// RFC5424 syslog
+  Syslog,
+  /// logfmt key=value pairs
+  Logfmt,
+  /// Any JSON object
+  Json,
+}
+
+impl LogFormat {
+  /// All built-in formats
+  pub const ALL: [LogFormat; 5] = [
+    LogFormat::Text,
+    LogFormat::Journald,
+    LogFormat::Syslog,
+    LogFormat::Logfmt,
+    LogFormat::Json,
+  ];
+  /// Code stored in `Entry.log_format`
+  pub fn code(self) -> i32 {
+    match self {
+      LogFormat::Text => 0,
+      LogFormat::Journald => 1,
+      LogFormat::Syslog => 2,
+      LogFormat::Logfmt => 3,
+      LogFormat::Json => 4,
+    }
+  }
+  /// Built-in format of a code
+  pub fn from_code(code: i32) -> Option<Self> {
+    Self::ALL.into_iter().find(|f| f.code() == code)
+  }
+}
+
+impl From<LogFormat> for i32 {
+  fn from(format: LogFormat) -> Self {
+    format.code()
+  }
+}
+
+/// Parses raw log text of one format into structured fields
+pub trait Parser: Send + Sync {
+  /// Structured fields of `log_entry`, `None` if it is not in this format
+  fn parse(&self, log_entry: &str) -> Option<Fields>;
+}
+
+/// Parsers by `log_format` code
+pub struct Registry {
+  parsers: HashMap<i32, Box<dyn Parser>>,
+}
+
+impl Registry {
+  /// Registry without any parser
+  pub fn empty() -> Self {
+    Self {
+      parsers: HashMap::new(),
+    }
+  }
+  /// Register the parser of a format code, replacing the previous one
+  ///
+  /// Custom formats should use codes from 100 on, lower codes
+  /// are reserved for built-in formats.
+  pub fn register<P>(&mut self, code: i32, parser: P)
+  where
+    P: Parser + 'static,
+  {
+    self.parsers.insert(code, Box::new(parser));
+  }
+  pub fn get(&self, code: i32) -> Option<&dyn Parser> {
+    self.parsers.get(&code).map(|p| p.as_ref())
+  }
+  /// Parse `log_entry` with the parser of `code`
+  pub fn parse(&self, code: i32, log_entry: &str) -> Option<Fields> {
+    self.get(code)?.parse(log_entry)
+  }
+  /// Fill the structured fields of an entry that has none yet
+  pub fn parse_entry(&self, entry: &mut Entry) {
+    if entry.fields.is_none() {
+      entry.fields = self.parse(entry.log_format, &entry.log_entry);
+    }
+  }
+}
+
+/// Registry with the built-in formats
+///
+/// Free text has no parser, its fields would only repeat the raw
+/// line. Register `TextParser` for it to get a message anyway.
+impl Default for Registry {
+  fn default() -> Self {
+    let mut res = Self::empty();
+    res.register(LogFormat::Journald.code(), JournaldParser);
+    res.register(LogFormat::Syslog.code(), SyslogParser);
+    res.register(LogFormat::Logfmt.code(), LogfmtParser);
+    res.register(LogFormat::Json.code(), JsonParser);
+    res
+  }
+}
+
+/// Field of `fields` a common key name belongs to, used by the
+/// key/value based formats (logfmt and generic JSON)
+fn set_common(fields: &mut Fields, key: &str, value: String) {
+  match key.to_ascii_lowercase().as_str() {
+    "level" | "lvl" | "severity" | "loglevel" => match Severity::from_name(&value) {
+      Some(severity) => fields.severity = Some(severity),
+      None => {
+        fields.extra.insert(key.to_string(), value);
+      }
+    },
+    "msg" | "message" => fields.message = Some(value),
+    "host" | "hostname" => fields.hostname = Some(value),
+    "app" | "unit" | "service" | "application" => fields.unit = Some(value),
+    "time" | "ts" | "timestamp" | "@timestamp" => match parse_time(&value) {
+      Some(time) => fields.timestamp = Some(time),
+      None => {
+        fields.extra.insert(key.to_string(), value);
+      }
+    },
+    "pid" => match value.parse() {
+      Ok(pid) => fields.pid = Some(pid),
+      Err(_) => {
+        fields.extra.insert(key.to_string(), value);
+      }
+    },
+    _ => {
+      fields.extra.insert(key.to_string(), value);
+    }
+  }
+}
+
+/// RFC3339 timestamp in UTC
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+  DateTime::parse_from_rfc3339(value)
+    .ok()
+    .map(|time| time.with_timezone(&Utc))
+}
@@ -0,0 +1,87 @@
+//! journald JSON and generic JSON objects
+use super::{set_common, Parser};
+use crate::fs::{Fields, Severity};
+use chrono::{DateTime, Utc};
+use serde_json::{Map, Value};
+
+/// Parses `journalctl -o json` lines
+///
+/// Trusted journal fields (leading underscore) win over the ones
+/// set by the client, e.g. `_PID` over `SYSLOG_PID`. The source time
+/// is `_SOURCE_REALTIME_TIMESTAMP`, or `__REALTIME_TIMESTAMP` when the
+/// client did not send one.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JournaldParser;
+
+impl Parser for JournaldParser {
+  fn parse(&self, log_entry: &str) -> Option<Fields> {
+    let mut res = Fields::default();
+    let mut unit = None;
+    let mut identifier = None;
+    let mut pid = None;
+    let mut syslog_pid = None;
+    let mut source_time = None;
+    let mut journal_time = None;
+    for (key, value) in object(log_entry)? {
+      let value = text(value);
+      match key.as_str() {
+        "PRIORITY" => res.severity = Severity::from_name(&value),
+        "_HOSTNAME" => res.hostname = Some(value),
+        "_SYSTEMD_UNIT" => unit = Some(value),
+        "SYSLOG_IDENTIFIER" => identifier = Some(value),
+        "_PID" => pid = value.parse().ok(),
+        "SYSLOG_PID" => syslog_pid = value.parse().ok(),
+        "MESSAGE" => res.message = Some(value),
+        "_SOURCE_REALTIME_TIMESTAMP" => source_time = micros(&value),
+        "__REALTIME_TIMESTAMP" => journal_time = micros(&value),
+        _ => {
+          res.extra.insert(key, value);
+        }
+      }
+    }
+    res.unit = unit.or(identifier);
+    res.pid = pid.or(syslog_pid);
+    res.timestamp = source_time.or(journal_time);
+    Some(res)
+  }
+}
+
+/// Parses any JSON object, mapping common keys like `level`,
+/// `msg` or `host`; other keys go into `extra`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonParser;
+
+impl Parser for JsonParser {
+  fn parse(&self, log_entry: &str) -> Option<Fields> {
+    let mut res = Fields::default();
+    for (key, value) in object(log_entry)? {
+      set_common(&mut res, &key, text(value));
+    }
+    Some(res)
+  }
+}
+
+fn object(log_entry: &str) -> Option<Map<String, Value>> {
+  match serde_json::from_str(log_entry) {
+    Ok(Value::Object(map)) => Some(map),
+    _ => None,
+  }
+}
+
+/// Journal timestamp, microseconds since the epoch
+fn micros(value: &str) -> Option<DateTime<Utc>> {
+  let micros: i64 = value.parse().ok()?;
+  let nanos = micros.rem_euclid(1_000_000) as u32 * 1000;
+  DateTime::from_timestamp(micros.div_euclid(1_000_000), nanos)
+}
+
+/// Strings as they are, other values as JSON text
+///
+/// journald stores binary fields as byte arrays, those are kept
+/// as JSON as well.
+fn text(value: Value) -> String {
+  match value {
+    Value::String(s) => s,
+    other => other.to_string(),
+  }
+}
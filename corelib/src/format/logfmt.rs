@@ -0,0 +1,66 @@
+//! logfmt, e.g. `level=info msg="user logged in" user=42`
+use super::{set_common, Parser};
+use crate::fs::Fields;
+
+/// Parses logfmt key=value pairs, mapping common keys like `level`,
+/// `msg` or `host`; other keys go into `extra`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogfmtParser;
+
+impl Parser for LogfmtParser {
+  fn parse(&self, log_entry: &str) -> Option<Fields> {
+    let pairs = pairs(log_entry)?;
+    let mut res = Fields::default();
+    for (key, value) in pairs {
+      set_common(&mut res, &key, value);
+    }
+    Some(res)
+  }
+}
+
+/// Key/value pairs of a line, `None` if it has none or a quoted
+/// value is not closed
+///
+/// A key without `=` is a flag with an empty value.
+fn pairs(line: &str) -> Option<Vec<(String, String)>> {
+  let mut res = Vec::new();
+  let mut chars = line.trim().chars().peekable();
+  loop {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    if chars.peek().is_none() {
+      break;
+    }
+    let mut key = String::new();
+    while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '=') {
+      key.push(c);
+    }
+    if key.is_empty() {
+      return None;
+    }
+    let mut value = String::new();
+    if chars.next_if_eq(&'=').is_some() {
+      if chars.next_if_eq(&'"').is_some() {
+        loop {
+          match chars.next()? {
+            '"' => break,
+            '\\' => match chars.next()? {
+              'n' => value.push('\n'),
+              't' => value.push('\t'),
+              c => value.push(c),
+            },
+            c => value.push(c),
+          }
+        }
+      } else {
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+          value.push(c);
+        }
+      }
+    }
+    res.push((key, value));
+  }
+  if res.is_empty() {
+    return None;
+  }
+  Some(res)
+}
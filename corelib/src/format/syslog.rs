@@ -0,0 +1,105 @@
+//! RFC5424 syslog
+//!
+//! `<PRI>VERSION TIMESTAMP HOSTNAME APP-NAME PROCID MSGID SD [MSG]`,
+//! where `-` stands for a missing value.
+use super::{parse_time, Parser};
+use crate::fs::{Fields, Severity};
+
+/// Parses RFC5424 syslog lines
+///
+/// Facility and message id go into `extra`, structured data
+/// parameters as `{sd id}.{name}`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SyslogParser;
+
+impl Parser for SyslogParser {
+  fn parse(&self, log_entry: &str) -> Option<Fields> {
+    let rest = log_entry.strip_prefix('<')?;
+    let (pri, rest) = rest.split_once('>')?;
+    let pri: i64 = pri.parse().ok().filter(|p| (0..=191).contains(p))?;
+    let (version, rest) = rest.split_once(' ')?;
+    if version.is_empty() || !version.bytes().all(|b| b.is_ascii_digit()) {
+      return None;
+    }
+    let (timestamp, rest) = rest.split_once(' ')?;
+    let (hostname, rest) = rest.split_once(' ')?;
+    let (app, rest) = rest.split_once(' ')?;
+    let (procid, rest) = rest.split_once(' ')?;
+    let (msgid, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+
+    let mut res = Fields {
+      severity: Severity::from_code(pri % 8),
+      hostname: nil(hostname).map(String::from),
+      unit: nil(app).map(String::from),
+      pid: nil(procid).and_then(|p| p.parse().ok()),
+      timestamp: nil(timestamp).and_then(parse_time),
+      ..Default::default()
+    };
+    res
+      .extra
+      .insert("facility".to_string(), (pri / 8).to_string());
+    if let Some(msgid) = nil(msgid) {
+      res.extra.insert("msgid".to_string(), msgid.to_string());
+    }
+    if let Some(procid) = nil(procid).filter(|_| res.pid.is_none()) {
+      res.extra.insert("procid".to_string(), procid.to_string());
+    }
+
+    let msg = match rest.strip_prefix('-') {
+      Some(msg) => msg,
+      None if rest.starts_with('[') => structured_data(rest, &mut res)?,
+      // Structured data is required, but may be empty at line end
+      None if rest.is_empty() => rest,
+      None => return None,
+    };
+    let msg = msg.strip_prefix(' ').unwrap_or(msg);
+    let msg = msg.strip_prefix('\u{feff}').unwrap_or(msg);
+    if !msg.is_empty() {
+      res.message = Some(msg.trim_end().to_string());
+    }
+    Some(res)
+  }
+}
+
+fn nil(value: &str) -> Option<&str> {
+  match value {
+    "-" => None,
+    value => Some(value),
+  }
+}
+
+/// Parse `[id name="value" ...]` elements into `extra`,
+/// returning the rest of the line
+fn structured_data<'a>(mut rest: &'a str, fields: &mut Fields) -> Option<&'a str> {
+  while let Some(element) = rest.strip_prefix('[') {
+    let end = element.find([' ', ']'])?;
+    let id = &element[..end];
+    rest = &element[end..];
+    loop {
+      rest = rest.trim_start_matches(' ');
+      if let Some(after) = rest.strip_prefix(']') {
+        rest = after;
+        break;
+      }
+      let (name, after) = rest.split_once("=\"")?;
+      let mut value = String::new();
+      let mut chars = after.char_indices();
+      let close = loop {
+        match chars.next()? {
+          (i, '"') => break i,
+          (_, '\\') => match chars.next()? {
+            (_, c @ ('"' | '\\' | ']')) => value.push(c),
+            (_, c) => {
+              value.push('\\');
+              value.push(c);
+            }
+          },
+          (_, c) => value.push(c),
+        }
+      };
+      fields.extra.insert(format!("{}.{}", id, name), value);
+      rest = &after[close + 1..];
+    }
+  }
+  Some(rest)
+}
@@ -0,0 +1,19 @@
+//! Free text
+use super::Parser;
+use crate::fs::Fields;
+
+/// Keeps the whole line as message
+///
+/// Not in the default `Registry`, as it stores every free text
+/// line twice.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TextParser;
+
+impl Parser for TextParser {
+  fn parse(&self, log_entry: &str) -> Option<Fields> {
+    Some(Fields {
+      message: Some(log_entry.trim_end().to_string()),
+      ..Default::default()
+    })
+  }
+}
@@ -3,21 +3,72 @@
 /// operations. Call these methods from a block_on
 /// code block to work with async code
 use chrono::{DateTime, Utc};
+use fs2::FileExt;
 use serde::{Deserialize, Serialize};
-use std::error::Error;
 use std::fs::{File, OpenOptions};
-use std::io::{BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
-use std::path::Path;
-use std::sync::{Arc, Mutex};
-use std::thread::spawn;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc::Sender;
-use tokio::task::spawn_blocking;
+
+use crate::Error;
+
+mod block;
+mod bloom;
+mod chain;
+mod crypto;
+mod fields;
+mod filter;
+mod iter;
+mod legacy;
+mod migrate;
+mod record;
+mod search;
+mod sparse;
+mod stats;
+mod time;
+
+pub use block::Compression;
+pub use bloom::KeyFilter;
+pub use chain::{verify, verify_with, ChainBreak, Digest, Verification};
+pub use crypto::{Cipher, Encryption, Key, KeyRing};
+pub use fields::{Fields, Severity};
+pub use filter::{Filter, TextMatch};
+pub use iter::{Iter, RevIter};
+pub use migrate::{migrate, migrate_to, migrate_to_with, migrate_with, Migration};
+pub use search::Query;
+pub use stats::Stats;
+pub use time::{Clock, TimeRange};
 
 const MAGIC: [u8; 9] = *b"towlfile*";
-const VERSION: i32 = 1;
+/// Towl format version
+///
+/// 1: raw bincode entries after the index region
+/// 2: length + CRC32 framed entries
+/// 3: framed index in a region sized by the header
+/// 4: optional block compression, selected in the header
+/// 5: optional encryption, cipher and key id stored in the header
+/// 6: optional hash chain over records
+/// 7: structured entry fields, blocks store entries one by one
+///
+/// Every version can be read. Writers append to version 7 files,
+/// older files are upgraded by `migrate`.
+const VERSION: i32 = 7;
+/// Oldest version that has the current layout
+const MIN_WRITE_VERSION: i32 = 7;
 const HEADER_START: u64 = 0;
-const INDEX_START: u64 = 1024;
-const INDEX_OFFSET: u64 = 1024;
+/// Size of the header region, the unused part is zero filled
+/// and reserved for future header fields
+const HEADER_LEN: u64 = 1024;
+const INDEX_START: u64 = HEADER_LEN;
+/// Size of the index region of new files
+const INDEX_CAPACITY: u64 = 64 * 1024;
+/// Zero bytes appended to a stored index or entry before decoding
+/// it, so fields added by later releases decode as empty
+const DECODE_SLACK: usize = 256;
+
+/// Byte ranges of a segment to read, with the earliest time in it
+pub(crate) type Segment = (DateTime<Utc>, Vec<(u64, u64)>);
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Header {
@@ -26,11 +77,61 @@ pub struct Header {
   pub org: String,
   pub title: String,
   pub id: usize,
+  /// Size of the index region in bytes
+  index_capacity: u64,
+  /// Compression of the data section
+  compression: Compression,
+  /// Cipher and key of encrypted files
+  encryption: Option<crypto::KeyInfo>,
+  /// Records are linked by a hash chain
+  hash_chain: bool,
 }
 
 impl Header {
-  fn check_magic(&self) -> bool {
-    self.magic == MAGIC
+  /// Byte offset of the data section
+  fn data_start(&self) -> u64 {
+    match self.version {
+      1 | 2 => legacy::DATA_START,
+      _ => INDEX_START + self.index_capacity,
+    }
+  }
+  /// Record codec of the file, unlocking its key from `keys`
+  fn codec(&self, keys: &KeyRing) -> crate::Result<block::Codec> {
+    let framing = match self.version {
+      1 => record::Framing::Bare,
+      _ => record::Framing::Framed,
+    };
+    let sealer = match &self.encryption {
+      Some(info) => Some(info.unlock(keys)?),
+      None => None,
+    };
+    Ok(block::Codec {
+      framing,
+      compression: self.compression,
+      sealer,
+      chained: self.hash_chain,
+      legacy_entries: self.version < 7,
+    })
+  }
+  /// Format version of the file
+  pub fn version(&self) -> i32 {
+    self.version
+  }
+  /// Compression of the data section
+  pub fn compression(&self) -> Compression {
+    self.compression
+  }
+  /// Cipher of an encrypted file
+  pub fn cipher(&self) -> Option<Cipher> {
+    self.encryption.as_ref().map(|info| info.cipher)
+  }
+  /// Id of the key an encrypted file was encrypted with
+  pub fn key_id(&self) -> Option<&str> {
+    self.encryption.as_ref().map(|info| info.key_id.as_str())
+  }
+  /// True if records are linked by a hash chain
+  pub fn hash_chain(&self) -> bool {
+    self.hash_chain
   }
 }
 
@@ -41,16 +142,61 @@ pub struct Index {
   count: usize,
   first_date: Option<DateTime<Utc>>,
   last_date: Option<DateTime<Utc>>,
+  /// Hash chain digest of the last record, set on close
+  digest: Option<Digest>,
+  /// Earliest and latest source time, see `Clock::Source`
+  first_source: Option<DateTime<Utc>>,
+  last_source: Option<DateTime<Utc>>,
+  /// Lowest and highest sequence number
+  first_seq: Option<u64>,
+  last_seq: Option<u64>,
+  stats: Stats,
 }
 
 impl Index {
-  fn add_entry(&mut self, entry: &Entry) {
-    match self.first_date {
-      Some(_) => self.last_date = Some(entry.received),
-      None => self.first_date = Some(entry.received),
+  fn new() -> Self {
+    Index {
+      opened: Utc::now(),
+      closed: None,
+      count: 0,
+      first_date: None,
+      last_date: None,
+      digest: None,
+      first_source: None,
+      last_source: None,
+      first_seq: None,
+      last_seq: None,
+      stats: Stats::default(),
+    }
+  }
+  /// Count `entry`, sender names of `encrypted` files are not kept
+  fn add_entry(&mut self, entry: &Entry, encrypted: bool) {
+    // Received times only run backwards with the server clock
+    let received = entry.received;
+    self.first_date = Some(
+      self
+        .first_date
+        .map_or(received, |first| first.min(received)),
+    );
+    self.last_date = Some(self.last_date.map_or(received, |last| last.max(received)));
+    // Source times of different hosts are not ordered
+    let source = entry.time(Clock::Source);
+    self.first_source = Some(self.first_source.map_or(source, |first| first.min(source)));
+    self.last_source = Some(self.last_source.map_or(source, |last| last.max(source)));
+    if entry.seq != 0 {
+      self.first_seq = Some(
+        self
+          .first_seq
+          .map_or(entry.seq, |first| first.min(entry.seq)),
+      );
+      self.last_seq = Some(self.last_seq.map_or(entry.seq, |last| last.max(entry.seq)));
     }
+    self.stats.add_entry(entry, encrypted);
     self.count += 1;
   }
+  fn add_record(&mut self, len: u64) {
+    self.stats.add_record(len);
+  }
   fn close(&mut self) {
     self.closed = Some(Utc::now());
   }
@@ -58,6 +204,58 @@ impl Index {
     self.count = 0;
     self.first_date = None;
     self.last_date = None;
+    self.first_source = None;
+    self.last_source = None;
+    self.first_seq = None;
+    self.last_seq = None;
+    self.stats = Stats::default();
+  }
+}
+
+impl Index {
+  /// Number of stored entries
+  pub fn count(&self) -> usize {
+    self.count
+  }
+  /// Creation time of the file
+  pub fn opened(&self) -> DateTime<Utc> {
+    self.opened
+  }
+  /// Time the file was closed, `None` while it is written
+  pub fn closed(&self) -> Option<DateTime<Utc>> {
+    self.closed
+  }
+  /// Final hash chain digest of a closed, chained file
+  pub fn digest(&self) -> Option<&Digest> {
+    self.digest.as_ref()
+  }
+  /// Earliest received or source time
+  pub fn first_date(&self, clock: Clock) -> Option<DateTime<Utc>> {
+    match clock {
+      Clock::Received => self.first_date,
+      // Indexes saved by older releases only know received times
+      Clock::Source => self.first_source.or(self.first_date),
+    }
+  }
+  /// Latest received or source time
+  pub fn last_date(&self, clock: Clock) -> Option<DateTime<Utc>> {
+    let last = self.last_date.or(self.first_date);
+    match clock {
+      Clock::Received => last,
+      Clock::Source => self.last_source.or(last),
+    }
+  }
+  /// Lowest sequence number, `None` if no entry has one
+  pub fn first_seq(&self) -> Option<u64> {
+    self.first_seq
+  }
+  /// Highest sequence number, `None` if no entry has one
+  pub fn last_seq(&self) -> Option<u64> {
+    self.last_seq
+  }
+  /// Statistics of the stored entries
+  pub fn stats(&self) -> &Stats {
+    &self.stats
   }
 }
 
@@ -66,29 +264,193 @@ pub struct Entry {
   pub sender: String,
   pub received: DateTime<Utc>,
   pub log_format: i32,
+  /// Raw log text
   pub log_entry: String,
+  /// Structured fields, if the entry was parsed
+  pub fields: Option<Fields>,
+  /// Global sequence number assigned by the server, increasing
+  /// across files; 0 if not assigned, e.g. in older files
+  pub seq: u64,
+}
+
+impl Entry {
+  /// Event time at the source, if the entry was parsed and has one
+  pub fn source_time(&self) -> Option<DateTime<Utc>> {
+    self.fields.as_ref().and_then(|f| f.timestamp)
+  }
+  /// Timestamp of the entry by the given clock
+  pub fn time(&self, clock: Clock) -> DateTime<Utc> {
+    match clock {
+      Clock::Received => self.received,
+      Clock::Source => self.source_time().unwrap_or(self.received),
+    }
+  }
+}
+
+/// Entry sent over gRPC, with its received time as RFC 3339 text
+#[cfg(feature = "proto")]
+impl From<Entry> for proto::towl::Entry {
+  fn from(entry: Entry) -> Self {
+    proto::towl::Entry {
+      sender: entry.sender,
+      received_rfc3339: entry.received.to_rfc3339(),
+      log_format: entry.log_format,
+      log_entry: entry.log_entry,
+      fields: entry.fields.map(Into::into),
+      seq: entry.seq,
+    }
+  }
+}
+
+/// Report of checking the data section
+#[derive(Debug, Clone, Default)]
+pub struct Recovery {
+  /// Number of valid entries found
+  pub entries: usize,
+  /// Offsets of damaged records, skipped by readers
+  pub damaged: Vec<u64>,
+  /// Offset of an incomplete tail, e.g. a write killed by a crash
+  pub tail: Option<u64>,
+  /// Number of tail bytes moved to the `.corrupt` sidecar
+  pub quarantined: u64,
+}
+
+impl Recovery {
+  /// True if no damaged record or torn tail was found
+  pub fn is_clean(&self) -> bool {
+    self.damaged.is_empty() && self.tail.is_none()
+  }
+}
+
+/// Options of new log files
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+  /// Compression of the data section, it cannot be changed later
+  pub compression: Compression,
+  /// Encrypt the data section with the given key
+  pub encryption: Option<Encryption>,
+  /// Link records by a hash chain, see `verify`
+  pub hash_chain: bool,
+}
+
+/// When appended entries are synced to disk
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Durability {
+  /// Never sync explicitly, the OS writes data back when it wants
+  #[default]
+  None,
+  /// Sync once at least n entries were added since the last sync
+  EveryN(usize),
+  /// Sync on append when the given time passed since the last sync
+  Interval(Duration),
+  /// Sync at the end of every append call
+  Always,
 }
 
 pub struct LogFile {
   pub header: Header,
   pub index: Index,
+  path: PathBuf,
   file: BufWriter<File>,
+  /// Byte offset of the data section
+  data_start: u64,
+  codec: block::Codec,
+  /// Digest of the last record of chained files
+  chain: Option<Digest>,
+  sparse: sparse::SparseIndex,
+  recovery: Recovery,
+  readonly: bool,
+  /// Exclusive lock on the `.lock` sidecar, held by writers
+  _writer_lock: Option<File>,
+  /// Byte offset where the next record is appended
+  write_offset: u64,
+  /// Entries of the block not written yet, see `add_entries`
+  block: Vec<Entry>,
+  durability: Durability,
+  /// Entries added since the last sync
+  unsynced: usize,
+  last_sync: Instant,
 }
 
 impl LogFile {
+  fn new(
+    header: Header,
+    index: Index,
+    path: PathBuf,
+    file: File,
+    writer_lock: Option<File>,
+    codec: block::Codec,
+  ) -> Self {
+    let data_start = header.data_start();
+    let chain = header.hash_chain.then_some(chain::GENESIS);
+    LogFile {
+      header,
+      index,
+      path,
+      file: BufWriter::new(file),
+      data_start,
+      codec,
+      chain,
+      sparse: sparse::SparseIndex::new(data_start),
+      recovery: Recovery::default(),
+      readonly: writer_lock.is_none(),
+      _writer_lock: writer_lock,
+      write_offset: data_start,
+      block: Vec::new(),
+      durability: Durability::default(),
+      unsynced: 0,
+      last_sync: Instant::now(),
+    }
+  }
   pub fn init(parent_path: &str, org: String, title: String, id: usize) -> crate::Result<Self> {
+    Self::init_with(parent_path, org, title, id, Options::default())
+  }
+  /// Init log file with the given options
+  pub fn init_with(
+    parent_path: &str,
+    org: String,
+    title: String,
+    id: usize,
+    options: Options,
+  ) -> crate::Result<Self> {
     let log_path = Path::new(parent_path).join(format!("{id}.towl"));
+    let keys = match &options.encryption {
+      Some(encryption) => KeyRing::from(encryption.key.clone()),
+      None => KeyRing::default(),
+    };
+
+    // Keep the writer lock across create and open,
+    // so no other writer gets in between
+    let mut log = Self::create(&log_path, org, title, id, options)?;
+    let writer_lock = log._writer_lock.take().expect("created writable");
+    drop(log);
+
+    // Open file
+    let file = OpenOptions::new().read(true).write(true).open(&log_path)?;
+    Self::open_locked(log_path, file, writer_lock, &keys)
+  }
+  /// Create an empty log file at `log_path` in the current version
+  fn create(
+    log_path: &Path,
+    org: String,
+    title: String,
+    id: usize,
+    options: Options,
+  ) -> crate::Result<Self> {
+    let log_path = log_path.to_path_buf();
 
     if log_path.exists() {
-      return Err("Log file have already exist.".to_string());
+      return Err(Error::AlreadyExists(log_path));
     }
 
+    // Lock before creating, so no other writer can open it half written
+    let writer_lock = lock_writer(&log_path)?;
     // Create file
-    let file = File::create(&log_path).map_err(|e| e.to_string())?;
-    // Set min file size
-    file
-      .set_len(INDEX_START + INDEX_OFFSET)
-      .map_err(|e| e.to_string())?;
+    let file = OpenOptions::new()
+      .read(true)
+      .write(true)
+      .create_new(true)
+      .open(&log_path)?;
     // Create header
     let header = Header {
       magic: MAGIC,
@@ -96,64 +458,153 @@ impl LogFile {
       org,
       title,
       id,
+      index_capacity: INDEX_CAPACITY,
+      compression: options.compression,
+      encryption: options.encryption.as_ref().map(crypto::KeyInfo::new),
+      hash_chain: options.hash_chain,
     };
-    // Create index
-    let index = Index {
-      opened: Utc::now(),
-      closed: None,
-      count: 0,
-      first_date: None,
-      last_date: None,
+    let keys = match options.encryption {
+      Some(encryption) => KeyRing::from(encryption.key),
+      None => KeyRing::default(),
     };
-    let mut res = LogFile {
+    let codec = header.codec(&keys)?;
+    // Set min file size
+    file.set_len(header.data_start())?;
+    // Create index
+    let index = Index::new();
+    let mut res = LogFile::new(
       header,
       index,
-      file: BufWriter::new(file),
-    };
+      log_path.clone(),
+      file,
+      Some(writer_lock),
+      codec,
+    );
     // Save header to disk
-    res.save_header()?;
-    // Save index to disk
-    res.save_index()?;
-
-    // Open file
-    Self::open(Path::new(parent_path).join(format!("{id}.towl")).as_path())
+    // Remove the file and its lock if it cannot be saved,
+    // e.g. a too long title
+    if let Err(e) = res.save_header().and_then(|_| res.save_index()) {
+      drop(res);
+      let _ = std::fs::remove_file(&log_path);
+      let _ = std::fs::remove_file(sidecar_path(&log_path, ".lock"));
+      return Err(e);
+    }
+    Ok(res)
   }
   pub fn open<T>(path: T) -> crate::Result<Self>
   where
     T: AsRef<Path>,
   {
-    // Open file with read write access
-    let mut file = OpenOptions::new()
-      .read(true)
-      .write(true)
-      .open(path)
-      .map_err(|e| e.to_string())?;
-
-    // Seek from start and read header
-    let _ = file.seek(SeekFrom::Start(HEADER_START));
-    let header: Header = bincode::deserialize_from(&file).map_err(|e| e.to_string())?;
+    Self::open_with(path, &KeyRing::default())
+  }
+  /// Open log file, taking the key of an encrypted file from `keys`
+  ///
+  /// Fails with `MissingKey` or `WrongKey` before anything is read
+  /// from the data section.
+  pub fn open_with<T>(path: T, keys: &KeyRing) -> crate::Result<Self>
+  where
+    T: AsRef<Path>,
+  {
+    let path = path.as_ref().to_path_buf();
 
-    if !header.check_magic() {
-      return Err("Not a towl log file. Magic error.".into());
-    }
+    // Open file with read write access; before locking,
+    // so a missing file leaves no lock sidecar behind
+    let file = OpenOptions::new().read(true).write(true).open(&path)?;
 
-    // Seek from index start position and read index
-    let _ = file.seek(SeekFrom::Start(INDEX_START));
-    let index: Index = bincode::deserialize_from(&file).map_err(|e| e.to_string())?;
+    // Only one writer at a time
+    let writer_lock = lock_writer(&path)?;
 
-    let mut res = LogFile {
-      header,
-      index,
-      file: BufWriter::new(file),
+    Self::open_locked(path, file, writer_lock, keys)
+  }
+  /// Open `file` for writing while holding its writer lock
+  fn open_locked(
+    path: PathBuf,
+    mut file: File,
+    writer_lock: File,
+    keys: &KeyRing,
+  ) -> crate::Result<Self> {
+    lock_shared(&file, &path)?;
+    let header = read_header(&mut file)?;
+    // Appending would mix layouts, older files must be migrated first
+    if header.version < MIN_WRITE_VERSION {
+      return Err(Error::NeedsMigrate(header.version));
+    }
+    let codec = header.codec(keys)?;
+    let index = match read_index(&mut file, &header) {
+      Ok(index) => index,
+      // Torn index write, count and dates are rebuilt by recovery
+      Err(Error::Corrupt { offset }) => {
+        log::warn!(
+          "{}: corrupt index at offset {}, rebuilding it",
+          path.display(),
+          offset
+        );
+        Index::new()
+      }
+      Err(e) => return Err(e),
     };
 
-    // Check if we need reindex
-    // If opened file is not closed, then we need to reindex it
-    res.reindex()?;
+    let mut res = LogFile::new(header, index, path, file, Some(writer_lock), codec);
+
+    // Rebuild index and cut off any torn tail
+    // left behind by a crash during write
+    res.recover()?;
+    res.write_offset = res.data_end()?;
 
     // Return self
     Ok(res)
   }
+  /// Open log file for reading only
+  ///
+  /// Nothing is written to the file, so it is safe on read-only mounts
+  /// and next to a live writer. The stored index is trusted; on a file
+  /// that is still being written it lags behind until `reindex` is
+  /// called, which then rebuilds it in memory only. Entries appended
+  /// after the last saved sparse index are still read, just unindexed.
+  pub fn open_readonly<T>(path: T) -> crate::Result<Self>
+  where
+    T: AsRef<Path>,
+  {
+    Self::open_readonly_with(path, &KeyRing::default())
+  }
+  /// Open log file for reading only, with the keys of encrypted files
+  pub fn open_readonly_with<T>(path: T, keys: &KeyRing) -> crate::Result<Self>
+  where
+    T: AsRef<Path>,
+  {
+    let path = path.as_ref().to_path_buf();
+
+    let mut file = File::open(&path)?;
+    lock_shared(&file, &path)?;
+    let header = read_header(&mut file)?;
+    let codec = header.codec(keys)?;
+    let index = read_index(&mut file, &header)?;
+
+    let mut res = LogFile::new(header, index, path, file, None, codec);
+
+    // Load sparse index; a closed file must have a complete one,
+    // otherwise it is rebuilt in memory. Sidecars of older versions
+    // may have a different layout, so they are never trusted.
+    match sparse::SparseIndex::load(&sidecar_path(&res.path, ".sparse")) {
+      Ok(sparse)
+        if res.header.version == VERSION
+          && (res.index.closed.is_none() || sparse.count() == res.index.count) =>
+      {
+        res.sparse = sparse
+      }
+      // Only the sparse index is rebuilt, a closed file's index is
+      // trusted as stored
+      _ => {
+        let index = res.index.closed.is_some().then(|| res.index.clone());
+        res.scan()?;
+        if let Some(index) = index {
+          res.index = index;
+        }
+      }
+    }
+
+    Ok(res)
+  }
   pub fn is_towl_file(path: &str) -> bool {
     let mut file = match OpenOptions::new().read(true).open(path) {
       Ok(f) => f,
@@ -162,7 +613,7 @@ impl LogFile {
 
     let _ = file.seek(SeekFrom::Start(HEADER_START));
     let mut _magic = [0, 0, 0, 0, 0, 0, 0, 0, 0];
-    let magic = match file.read_exact(&mut _magic) {
+    match file.read_exact(&mut _magic) {
       Ok(_) => (),
       Err(_) => return false,
     };
@@ -171,80 +622,679 @@ impl LogFile {
   }
   /// Close log file
   pub fn close(&mut self) -> crate::Result<()> {
+    self.check_writable()?;
+    self.write_open_block()?;
     self.index.close();
-    self.save_index()
+    self.index.digest = self.chain;
+    self.save_index()?;
+    self.sync()?;
+    self.build_text_index()
+  }
+  /// Build the full text index sidecar of all entries, see `search`
+  ///
+  /// Done on `close`; call it again to rebuild a lost or
+  /// outdated sidecar.
+  pub fn build_text_index(&mut self) -> crate::Result<()> {
+    self.check_writable()?;
+    self.flush()?;
+    let index = search::TextIndex::build(self)?;
+    index.save(&sidecar_path(&self.path, ".idx"), &self.codec)
   }
   fn save_header(&mut self) -> crate::Result<()> {
+    // Serialize header and zero fill the rest of its region
+    let mut bytes = bincode::serialize(&self.header).map_err(Error::Encode)?;
+    check_region("header", bytes.len(), HEADER_LEN)?;
+    bytes.resize(HEADER_LEN as usize, 0);
     // Set cursor to 0 bytes
-    let _ = self.file.seek(SeekFrom::Start(HEADER_START));
-    let header = self.header.clone();
-    // Serialize header
-    bincode::serialize_into(self.file.get_mut(), &header).map_err(|e| e.to_string())?;
+    self.file.seek(SeekFrom::Start(HEADER_START))?;
+    self.file.write_all(&bytes)?;
     // Flush file
-    self.flush().map_err(|e| e.to_string())?;
+    self.flush()?;
     Ok(())
   }
   fn save_index(&mut self) -> crate::Result<()> {
-    // Set cursor to 0 bytes
-    let _ = self.file.seek(SeekFrom::Start(INDEX_START));
-    // Serialize index
-    bincode::serialize_into(self.file.get_mut(), &self.index).map_err(|e| e.to_string())?;
+    // Serialize index as a framed record, so a torn write is detected
+    let payload = bincode::serialize(&self.index).map_err(Error::Encode)?;
+    let bytes = record::frame(&payload);
+    check_region("index", bytes.len(), self.header.index_capacity)?;
+    // Set cursor to index start
+    self.file.seek(SeekFrom::Start(INDEX_START))?;
+    self.file.write_all(&bytes)?;
     // Flush file
-    self.flush().map_err(|e| e.to_string())?;
+    self.flush()?;
+    // Save sparse index next to it
+    self.sparse.save(&sidecar_path(&self.path, ".sparse"))?;
     Ok(())
   }
-  fn flush(&mut self) -> Result<(), Box<dyn Error>> {
-    self.file.get_mut().flush()?;
+  fn flush(&mut self) -> crate::Result<()> {
+    self.file.flush()?;
+    Ok(())
+  }
+  fn check_writable(&self) -> crate::Result<()> {
+    if self.readonly {
+      return Err(Error::ReadOnly);
+    }
     Ok(())
   }
+  /// Set when appended entries are synced to disk
+  pub fn set_durability(&mut self, durability: Durability) {
+    self.durability = durability;
+  }
   /// Add entry to log file
   pub fn add_entry(&mut self, entry: Entry) -> crate::Result<()> {
+    self.add_entries(std::slice::from_ref(&entry))
+  }
+  /// Add a batch of entries to log file
+  ///
+  /// Records go through the write buffer and reach the OS with a
+  /// single flush at the end; whether they are synced to disk as a
+  /// group commit depends on the `Durability` setting.
+  /// In compressed files entries are collected in an open block
+  /// first, which is written once it is full, on `sync` and on
+  /// `close`. Its entries are not read back before that.
+  ///
+  /// A batch with an entry too large for a record is rejected with
+  /// `Error::EntryTooLarge` before any of it is written.
+  pub fn add_entries(&mut self, entries: &[Entry]) -> crate::Result<()> {
+    self.check_writable()?;
+    for entry in entries {
+      block::entry_size(entry)?;
+    }
+    self.block.extend_from_slice(entries);
+    while let Some(len) = self.codec.full_block(&self.block) {
+      let block: Vec<Entry> = self.block.drain(..len).collect();
+      self.write_block(&block)?;
+    }
+    self.flush()?;
+
+    self.unsynced += entries.len();
+    let sync_due = match self.durability {
+      Durability::None => false,
+      Durability::EveryN(n) => self.unsynced >= n,
+      Durability::Interval(interval) => self.last_sync.elapsed() >= interval,
+      Durability::Always => true,
+    };
+    if sync_due {
+      self.sync()?;
+    }
+
+    Ok(())
+  }
+  /// Write a block as a single framed record at the end of the data
+  fn write_block(&mut self, block: &[Entry]) -> crate::Result<()> {
+    // Serialize block
+    let mut payload = self.codec.encode(block)?;
+    let digest = self.chain.map(|prev| chain::link(&prev, &payload));
+    if let Some(digest) = digest {
+      payload.splice(0..0, digest);
+    }
+    // Readers take a longer record for a torn one. Entry and block
+    // sizes keep below the limit, this only guards against a bug.
+    if payload.len() > record::MAX_PAYLOAD_LEN as usize {
+      return Err(Error::EntryTooLarge {
+        size: payload.len() as u64,
+        max: record::MAX_PAYLOAD_LEN as u64,
+      });
+    }
+    if digest.is_some() {
+      self.chain = digest;
+    }
+    let record = record::frame(&payload);
     // Set cursor to the end
-    self.file.seek(SeekFrom::End(0)).unwrap();
-    // Serialize entry into it
-    bincode::serialize_into(self.file.get_mut(), &entry).map_err(|e| e.to_string())?;
+    self.file.seek(SeekFrom::Start(self.write_offset))?;
+    self.file.write_all(&record)?;
     // Update index
     // We need to save index when we close this logfile
-    self.index.add_entry(&entry);
-
+    let encrypted = self.header.encryption.is_some();
+    for entry in block {
+      self.index.add_entry(entry, encrypted);
+    }
+    self.index.add_record(record.len() as u64);
+    self
+      .sparse
+      .add_record(self.write_offset, record.len() as u64, block, encrypted);
+    self.write_offset += record.len() as u64;
+    Ok(())
+  }
+  /// Write the open block of a compressed file, even if not full
+  fn write_open_block(&mut self) -> crate::Result<()> {
+    if !self.block.is_empty() {
+      let block = std::mem::take(&mut self.block);
+      self.write_block(&block)?;
+    }
+    Ok(())
+  }
+  /// Write the open block, flush buffered records and sync them to disk
+  pub fn sync(&mut self) -> crate::Result<()> {
+    self.check_writable()?;
+    self.write_open_block()?;
+    self.flush()?;
+    self.file.get_ref().sync_data()?;
+    self.unsynced = 0;
+    self.last_sync = Instant::now();
     Ok(())
   }
   pub fn header(&self) -> &Header {
     &self.header
   }
-  pub fn reindex(&mut self) -> crate::Result<()> {
-    // Set offset to start position
-    self
-      .file
-      .seek(SeekFrom::Start(INDEX_START + INDEX_OFFSET))
-      .map_err(|e| e.to_string())?;
+  /// Report of the recovery performed when the file was opened
+  pub fn recovery(&self) -> &Recovery {
+    &self.recovery
+  }
+  /// Whether the entries are stored in received order, false if
+  /// the server clock went backwards while writing
+  pub(crate) fn monotonic(&self) -> bool {
+    self.sparse.monotonic()
+  }
+  /// Rebuild index from the stored entries
+  ///
+  /// Damaged records are skipped, counting stops at a torn tail.
+  /// The data section itself is not modified, and on a read-only
+  /// handle the rebuilt index is kept in memory only.
+  pub fn reindex(&mut self) -> crate::Result<Recovery> {
+    let report = self.scan()?;
 
+    // Save index
+    if !self.readonly {
+      self.save_index()?;
+    }
+    Ok(report)
+  }
+  /// Rebuild index and sparse index in memory
+  fn scan(&mut self) -> crate::Result<Recovery> {
     // Reset index
     self.index.reset();
+    self.sparse = sparse::SparseIndex::new(self.data_start);
+    if self.chain.is_some() {
+      self.chain = Some(chain::GENESIS);
+    }
+    let mut report = Recovery::default();
+
+    let encrypted = self.header.encryption.is_some();
+
+    // Set offset to start position
+    self.file.seek(SeekFrom::Start(self.data_start))?;
+    let reader = BufReader::new(self.file.get_mut());
+    let mut reader =
+      record::RecordReader::with_framing(reader, self.data_start, self.codec.framing);
 
     // Stream log entries
-    while let Ok(entry) = bincode::deserialize_from::<&mut File, Entry>(&mut self.file.get_mut()) {
-      self.index.add_entry(&entry);
+    while let Some(frame) = reader.next_frame()? {
+      match frame {
+        record::Frame::Record { offset, payload } => {
+          // Checksum is fine, but the payload may still not decode,
+          // e.g. when it was rewritten along with its checksum
+          let entries = match self.codec.decode(&payload) {
+            Ok(entries) => entries,
+            Err(_) => {
+              report.damaged.push(offset);
+              continue;
+            }
+          };
+          let len = reader.offset() - offset;
+          // New records continue the chain from the last valid one
+          if let Some(head) = &mut self.chain {
+            *head = chain::split(&payload)?.0;
+          }
+          for entry in &entries {
+            self.index.add_entry(entry, encrypted);
+          }
+          self.index.add_record(len);
+          self.sparse.add_record(offset, len, &entries, encrypted);
+        }
+        record::Frame::Damaged { offset } => report.damaged.push(offset),
+        record::Frame::Torn { offset } => report.tail = Some(offset),
+      }
     }
+    report.entries = self.index.count;
 
-    // Save index
-    self.save_index()?;
+    Ok(report)
+  }
+  /// Reindex and truncate an incomplete tail
+  ///
+  /// Tail bytes are appended to the `{path}.corrupt` sidecar
+  /// before truncation, so nothing is lost for good
+  fn recover(&mut self) -> crate::Result<()> {
+    let mut report = self.reindex()?;
+
+    if let Some(tail) = report.tail {
+      let file = self.file.get_mut();
+      let len = file.metadata()?.len();
+
+      // Move tail into quarantine
+      let mut sidecar = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(sidecar_path(&self.path, ".corrupt"))?;
+      file.seek(SeekFrom::Start(tail))?;
+      report.quarantined = std::io::copy(&mut file.take(len - tail), &mut sidecar)?;
+      sidecar.sync_data()?;
+
+      // Cut it off from the data file
+      file.set_len(tail)?;
+      file.sync_data()?;
+
+      log::warn!(
+        "{}: truncated torn tail at offset {}, {} bytes quarantined",
+        self.path.display(),
+        tail,
+        report.quarantined
+      );
+    }
+
+    if !report.damaged.is_empty() {
+      log::warn!(
+        "{}: damaged records at offsets {:?}",
+        self.path.display(),
+        report.damaged
+      );
+    }
+
+    self.recovery = report;
     Ok(())
   }
-  pub fn stream<'a>(&'a mut self, after_dt: DateTime<Utc>, tx: Sender<Entry>) -> crate::Result<()> {
-    // Set offset to start position
-    self
-      .file
-      .seek(SeekFrom::Start(INDEX_START + INDEX_OFFSET))
-      .map_err(|e| e.to_string())?;
+  /// Byte offset of the record holding the entry at position `n` (0 based)
+  ///
+  /// Jumps to the nearest sparse index point and only walks
+  /// the records after it. Returns the end of data when `n`
+  /// is not smaller than the entry count.
+  pub fn seek_to_count(&self, n: usize) -> crate::Result<u64> {
+    Ok(self.locate(n)?.0)
+  }
+  /// Record offset of entry `n`, and the number
+  /// of entries before it in the same record
+  fn locate(&self, n: usize) -> crate::Result<(u64, usize)> {
+    let end = self.data_end()?;
+    let (mut count, start) = self.sparse.lookup(n);
 
-    // Stream log entries
-    while let Ok(entry) = bincode::deserialize_from::<&mut File, Entry>(&mut self.file.get_mut()) {
-      if entry.received > after_dt {
-        tx.blocking_send(entry)
-          .expect("Error sending entry to reader via tokio channel");
+    let mut file = File::open(&self.path)?;
+    file.seek(SeekFrom::Start(start))?;
+    let reader = BufReader::new(file.take(end - start));
+    let mut reader = record::RecordReader::with_framing(reader, start, self.codec.framing);
+
+    while let Some(frame) = reader.next_frame()? {
+      match frame {
+        record::Frame::Record { offset, payload } => {
+          let entries = self.codec.count(&payload)?;
+          if n < count + entries {
+            return Ok((offset, n - count));
+          }
+          count += entries;
+        }
+        record::Frame::Damaged { .. } => (),
+        // Write in progress
+        record::Frame::Torn { offset } if self.index.closed.is_none() => return Ok((offset, 0)),
+        record::Frame::Torn { offset } => return Err(Error::Corrupt { offset }),
       }
     }
-    Ok(())
+
+    Ok((reader.offset(), 0))
+  }
+  /// Iterate over all entries
+  pub fn iter(&self) -> crate::Result<Iter> {
+    self.iter_from(0)
+  }
+  /// Iterate over entries starting at position `n` (0 based)
+  pub fn iter_from(&self, n: usize) -> crate::Result<Iter> {
+    let (start, skip) = self.locate(n)?;
+    let end = self.data_end()?;
+    let iter = Iter::new(
+      File::open(&self.path)?,
+      self.codec.clone(),
+      self.index.closed.is_none(),
+      vec![(start, end)],
+      Filter::default(),
+    );
+    Ok(iter.skip_entries(skip))
+  }
+  /// Iterate over entries received after `after_dt`
+  ///
+  /// Only the segments that may hold such entries are read,
+  /// see the sparse index
+  pub fn iter_after(&self, after_dt: DateTime<Utc>) -> crate::Result<Iter> {
+    self.iter_range(TimeRange::default().after(after_dt))
+  }
+  /// Iterate over entries in a time range, by received or source time
+  ///
+  /// Like `iter_after`, segments that cannot hold matching entries
+  /// are skipped. Entries come in file order, which is not sorted
+  /// by source time.
+  pub fn iter_range(&self, range: TimeRange) -> crate::Result<Iter> {
+    self.iter_filter(Filter::new().range(range))
+  }
+  /// Iterate over entries in a time range with the given senders,
+  /// units or severities
+  ///
+  /// Segments whose bloom filter rules out every wanted value of a
+  /// key are skipped, e.g. when reading the entries of a single host.
+  pub fn iter_keys(&self, range: TimeRange, keys: KeyFilter) -> crate::Result<Iter> {
+    self.iter_filter(Filter {
+      range,
+      keys,
+      ..Filter::default()
+    })
+  }
+  /// Iterate over entries with a sequence number above `seq`
+  ///
+  /// Used to resume a stream after the last entry a client got.
+  /// Only segments holding higher sequence numbers are read.
+  pub fn iter_after_seq(&self, seq: u64) -> crate::Result<Iter> {
+    self.iter_filter(Filter::new().after_seq(seq))
+  }
+  /// Iterate over entries whose raw text matches `query`
+  ///
+  /// With the full text index sidecar only the records holding every
+  /// query term are read, plus the entries appended after it was
+  /// built. Without it, every entry is checked.
+  pub fn search(&self, query: Query) -> crate::Result<Iter> {
+    self.iter_filter(Filter::new().query(query))
+  }
+  /// Iterate over entries matching `filter`
+  ///
+  /// The filter is checked while decoding, and the byte ranges read
+  /// are narrowed by every index that can answer a part of it: the
+  /// sparse index for time and sequence number, the bloom filters of
+  /// segments for senders, units and severities, and the full text
+  /// index for a query.
+  pub fn iter_filter(&self, filter: Filter) -> crate::Result<Iter> {
+    let end = self.data_end()?;
+    let mut ranges = self
+      .sparse
+      .ranges_in(&filter.range, &filter.block_keys(), end);
+    if let Some(narrow) = self.narrowing(&filter, end) {
+      ranges = intersect(&ranges, &narrow);
+    }
+    self.iter_ranges(ranges, filter)
+  }
+  /// Iterate over entries matching `filter`, starting at position `n`
+  /// (0 based)
+  ///
+  /// Like `iter_filter`, so a client resuming after the entries it
+  /// already has still gets the segments skipped that cannot match.
+  pub fn iter_filter_from(&self, n: usize, filter: Filter) -> crate::Result<Iter> {
+    let end = self.data_end()?;
+    let (start, skip) = self.locate(n)?;
+    let ranges = self
+      .sparse
+      .ranges_in(&filter.range, &filter.block_keys(), end);
+    let mut ranges = intersect(&ranges, &[(start, end)]);
+    if let Some(narrow) = self.narrowing(&filter, end) {
+      ranges = intersect(&ranges, &narrow);
+    }
+    // The entries before `n` in its record are only there to skip
+    // when the record is read
+    let skip = match ranges.first() {
+      Some((first, _)) if *first == start => skip,
+      _ => 0,
+    };
+    Ok(self.iter_ranges(ranges, filter)?.skip_entries(skip))
+  }
+  /// Segments that may hold entries matching `filter`, each with the
+  /// earliest time of the filter's clock in it, ordered by that time
+  ///
+  /// The unindexed tail comes first, its times are not known.
+  /// Read them with `iter_ranges` to merge entries that are not
+  /// stored in time order.
+  pub(crate) fn segments_by_time(&self, filter: &Filter) -> crate::Result<Vec<Segment>> {
+    let end = self.data_end()?;
+    let narrow = self.narrowing(filter, end);
+    let mut res: Vec<_> = self
+      .sparse
+      .segments_in(&filter.range, &filter.block_keys(), end)
+      .into_iter()
+      .map(|(first, range)| match &narrow {
+        Some(narrow) => (first, intersect(&[range], narrow)),
+        None => (first, vec![range]),
+      })
+      .filter(|(_, ranges)| !ranges.is_empty())
+      .collect();
+    res.sort_by_key(|(first, _)| *first);
+    Ok(res)
+  }
+  /// Iterate over the entries matching `filter` in the given byte
+  /// ranges, which start and end at record boundaries
+  pub(crate) fn iter_ranges(&self, ranges: Vec<(u64, u64)>, filter: Filter) -> crate::Result<Iter> {
+    Ok(Iter::new(
+      File::open(&self.path)?,
+      self.codec.clone(),
+      self.index.closed.is_none(),
+      ranges,
+      filter,
+    ))
+  }
+  /// Byte ranges left by the sequence number and the full text query
+  /// of `filter`, `None` if it has neither
+  fn narrowing(&self, filter: &Filter, end: u64) -> Option<Vec<(u64, u64)>> {
+    let seq = filter
+      .after_seq
+      .map(|seq| self.sparse.ranges_after_seq(seq, end));
+    let text = filter
+      .query
+      .as_ref()
+      .map(|query| self.text_ranges(query, end));
+    match (seq, text) {
+      (Some(seq), Some(text)) => Some(intersect(&seq, &text)),
+      (seq, text) => seq.or(text),
+    }
+  }
+  /// Byte ranges that may hold entries matching `query`, by the
+  /// full text index sidecar if it belongs to this file
+  fn text_ranges(&self, query: &Query, end: u64) -> Vec<(u64, u64)> {
+    let index = search::TextIndex::load(&sidecar_path(&self.path, ".idx"), &self.codec);
+    match index {
+      Ok(index) if index.covers(self, end) => {
+        let mut ranges = index.ranges(query);
+        // Entries appended since the index was built are scanned
+        if end > index.end {
+          match ranges.last_mut() {
+            Some((_, last_end)) if *last_end == index.end => *last_end = end,
+            _ => ranges.push((index.end, end)),
+          }
+        }
+        ranges
+      }
+      _ => vec![(self.data_start, end)],
+    }
+  }
+  /// Iterate over the newest `n` entries, oldest first
+  ///
+  /// Like `tail -n`, the start position is found via the sparse index
+  pub fn tail(&self, n: usize) -> crate::Result<Iter> {
+    self.iter_from(self.index.count.saturating_sub(n))
+  }
+  /// Iterate over all entries backwards, newest first
+  pub fn iter_rev(&self) -> crate::Result<RevIter> {
+    let segments = self.sparse.segments(self.data_end()?);
+    Ok(RevIter::new(
+      File::open(&self.path)?,
+      self.codec.clone(),
+      self.index.closed.is_none(),
+      segments,
+    ))
+  }
+  /// Stream entries received after `after_dt` into `tx`
+  ///
+  /// Returns `Error::Corrupt` when a damaged record is found; every
+  /// valid entry before it has already been sent. Stops without
+  /// error when the receiver is dropped.
+  pub fn stream(&self, after_dt: DateTime<Utc>, tx: Sender<Entry>) -> crate::Result<()> {
+    send(self.iter_after(after_dt)?, tx)
+  }
+  /// Stream entries after the first `count` entries into `tx`
+  ///
+  /// Used for partial sync, the start position is found
+  /// via `seek_to_count`
+  pub fn stream_after_count(&self, count: usize, tx: Sender<Entry>) -> crate::Result<()> {
+    send(self.iter_from(count)?, tx)
+  }
+  /// Stream entries with a sequence number above `seq` into `tx`
+  pub fn stream_after_seq(&self, seq: u64, tx: Sender<Entry>) -> crate::Result<()> {
+    send(self.iter_after_seq(seq)?, tx)
+  }
+  /// Stream entries matching `filter` into `tx`
+  ///
+  /// Entries are filtered on the calling thread, so only matching
+  /// ones are sent.
+  pub fn stream_filter(&self, filter: Filter, tx: Sender<Entry>) -> crate::Result<()> {
+    send(self.iter_filter(filter)?, tx)
+  }
+  /// End of the data section
+  fn data_end(&self) -> crate::Result<u64> {
+    Ok(self.file.get_ref().metadata()?.len())
+  }
+}
+
+impl Drop for LogFile {
+  fn drop(&mut self) {
+    // Entries of the open block are lost otherwise
+    if let Err(e) = self.write_open_block().and_then(|_| self.flush()) {
+      log::error!("{}: cannot write open block: {}", self.path.display(), e);
+    }
+  }
+}
+
+/// Take the exclusive writer lock of a towl file
+///
+/// Writers lock the `{path}.lock` sidecar instead of the file itself,
+/// so read-only handles are not blocked by a live writer
+pub(crate) fn lock_writer(path: &Path) -> crate::Result<File> {
+  let lock_path = sidecar_path(path, ".lock");
+  let lock = OpenOptions::new()
+    .create(true)
+    .truncate(false)
+    .write(true)
+    .open(&lock_path)?;
+  FileExt::try_lock_exclusive(&lock).map_err(|e| lock_error(e, path))?;
+  Ok(lock)
+}
+
+/// Take a shared lock on the towl file itself
+///
+/// Held by every handle, it keeps tools that rewrite a file
+/// in place (and take an exclusive lock) away while it is in use
+fn lock_shared(file: &File, path: &Path) -> crate::Result<()> {
+  FileExt::try_lock_shared(file).map_err(|e| lock_error(e, path))
+}
+
+fn lock_error(e: std::io::Error, path: &Path) -> Error {
+  if e.kind() == fs2::lock_contended_error().kind() {
+    Error::Locked(path.to_path_buf())
+  } else {
+    Error::Io(e)
   }
 }
+
+/// Check that a serialized region fits into its capacity
+fn check_region(region: &'static str, size: usize, capacity: u64) -> crate::Result<()> {
+  if size as u64 > capacity {
+    return Err(Error::RegionOverflow {
+      region,
+      size: size as u64,
+      capacity,
+    });
+  }
+  Ok(())
+}
+
+/// Read header and stored index of a log file, without its data
+///
+/// Like `open_readonly` it takes a shared lock only, and needs no key.
+pub(crate) fn read_meta(path: &Path) -> crate::Result<(Header, Index)> {
+  let mut file = File::open(path)?;
+  lock_shared(&file, path)?;
+  let header = read_header(&mut file)?;
+  let index = read_index(&mut file, &header)?;
+  Ok((header, index))
+}
+
+/// Read and check the header of a towl file
+fn read_header(file: &mut File) -> crate::Result<Header> {
+  // Seek from start and read header region
+  let mut region = Vec::with_capacity(HEADER_LEN as usize);
+  file.seek(SeekFrom::Start(HEADER_START))?;
+  (&mut *file).take(HEADER_LEN).read_to_end(&mut region)?;
+
+  if !region.starts_with(&MAGIC) {
+    return Err(Error::BadMagic);
+  }
+
+  // Version follows the magic in every version
+  let version = region
+    .get(MAGIC.len()..MAGIC.len() + 4)
+    .map(|v| i32::from_le_bytes([v[0], v[1], v[2], v[3]]))
+    .ok_or(Error::Corrupt {
+      offset: MAGIC.len() as u64,
+    })?;
+  if !(1..=VERSION).contains(&version) {
+    return Err(Error::UnsupportedVersion(version));
+  }
+
+  let header: Header = bincode::deserialize(&region)?;
+  Ok(header)
+}
+
+/// Read the index of a towl file
+fn read_index(file: &mut File, header: &Header) -> crate::Result<Index> {
+  // Seek from index start position and read index
+  file.seek(SeekFrom::Start(INDEX_START))?;
+  if header.version < 3 {
+    let index: legacy::IndexV1 = bincode::deserialize_from(BufReader::new(&mut *file))?;
+    return Ok(index.into());
+  }
+  let reader = BufReader::new((&mut *file).take(header.index_capacity));
+  match record::RecordReader::new(reader, INDEX_START).next_frame()? {
+    Some(record::Frame::Record { payload, .. }) => decode_padded(payload),
+    _ => Err(Error::Corrupt {
+      offset: INDEX_START,
+    }),
+  }
+}
+
+/// Decode bincode data written by this or an older release,
+/// trailing fields missing from it decode from zero bytes
+///
+/// Only fields appended at the very end of the encoded value work
+/// this way. A field added to a nested struct shifts the fields
+/// encoded after it, e.g. `seq` after the `Fields` of an `Entry`,
+/// so changing `Fields` needs a new `VERSION`.
+fn decode_padded<T: serde::de::DeserializeOwned>(mut bytes: Vec<u8>) -> crate::Result<T> {
+  bytes.resize(bytes.len() + DECODE_SLACK, 0);
+  Ok(bincode::deserialize(&bytes)?)
+}
+
+/// Send entries into a tokio channel from a blocking thread
+fn send(entries: Iter, tx: Sender<Entry>) -> crate::Result<()> {
+  for entry in entries {
+    if tx.blocking_send(entry?).is_err() {
+      // Receiver is gone, nobody needs the rest
+      break;
+    }
+  }
+  Ok(())
+}
+
+/// Overlap of two sorted lists of disjoint byte ranges
+fn intersect(a: &[(u64, u64)], b: &[(u64, u64)]) -> Vec<(u64, u64)> {
+  let mut res = Vec::new();
+  let (mut i, mut j) = (0, 0);
+  while i < a.len() && j < b.len() {
+    let start = a[i].0.max(b[j].0);
+    let end = a[i].1.min(b[j].1);
+    if start < end {
+      res.push((start, end));
+    }
+    if a[i].1 < b[j].1 {
+      i += 1;
+    } else {
+      j += 1;
+    }
+  }
+  res
+}
+
+/// Path of a sidecar file next to the log file, e.g. `1.towl.corrupt`
+pub(crate) fn sidecar_path(path: &Path, ext: &str) -> PathBuf {
+  let mut p = path.as_os_str().to_owned();
+  p.push(ext);
+  PathBuf::from(p)
+}
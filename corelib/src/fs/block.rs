@@ -0,0 +1,186 @@
+//! Entry blocks stored in the data section records
+//!
+//! Without compression every record holds a single bincode entry.
+//! With compression entries are grouped into blocks, each record
+//! payload is
+//!
+//! | bytes | content |
+//! | --- | --- |
+//! | 4 | number of entries, u32 little endian |
+//! | rest | compressed bincode `Vec<Vec<u8>>` of bincode entries |
+//!
+//! so a block can be counted without decompressing it. As every entry
+//! keeps its own length, entries written before a field was added are
+//! decoded with zero bytes for it, like the index. Files before
+//! version 7 store blocks as a plain `Vec<Entry>` of the old layout.
+//! In encrypted files the payload is sealed afterwards, see `crypto`,
+//! and in chained files it is prefixed with its digest, see `chain`.
+use super::chain;
+use super::crypto::Sealer;
+use super::legacy::EntryV1;
+use super::record::{Framing, MAX_PAYLOAD_LEN};
+use super::{decode_padded, Entry};
+use crate::Error;
+use serde::{Deserialize, Serialize};
+
+/// Most entries stored in one block
+const BLOCK_ENTRIES: usize = 256;
+/// A block is closed once its entries reach this many bytes
+const BLOCK_BYTES: u64 = 1024 * 1024;
+/// Most serialized entry bytes in one record. The rest of
+/// `MAX_PAYLOAD_LEN` is left for the block layout, compression
+/// overhead of incompressible data, the cipher and the chain digest.
+pub(crate) const MAX_ENTRY_BYTES: u64 = MAX_PAYLOAD_LEN as u64 / 64 * 63 - 4096;
+/// zstd compression level
+const ZSTD_LEVEL: i32 = 3;
+
+/// Compression of the data section, chosen in the header
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Compression {
+  /// One bincode entry per record
+  #[default]
+  None,
+  /// zstd compressed blocks
+  Zstd,
+  /// lz4 compressed blocks
+  Lz4,
+}
+
+/// How records of a file are read and written
+#[derive(Clone, Debug)]
+pub(crate) struct Codec {
+  pub framing: Framing,
+  pub compression: Compression,
+  /// Cipher of encrypted files, with the unlocked key
+  pub sealer: Option<Sealer>,
+  /// Payloads start with a hash chain digest
+  pub chained: bool,
+  /// Entries use the layout before version 7
+  pub legacy_entries: bool,
+}
+
+/// Serialized size of an entry, an error if no record can hold it
+pub(crate) fn entry_size(entry: &Entry) -> crate::Result<u64> {
+  let size = bincode::serialized_size(entry).map_err(Error::Encode)?;
+  if size > MAX_ENTRY_BYTES {
+    return Err(Error::EntryTooLarge {
+      size,
+      max: MAX_ENTRY_BYTES,
+    });
+  }
+  Ok(size)
+}
+
+impl Codec {
+  /// Length of the first full block at the start of `entries`
+  ///
+  /// Entries short of a full block stay in the open block of the
+  /// writer. Without compression every entry is a block of its own.
+  /// A block is also closed before an entry that would take its
+  /// serialized size over `MAX_ENTRY_BYTES`, so every block fits
+  /// into a record whatever its entries compress to.
+  pub(crate) fn full_block(&self, entries: &[Entry]) -> Option<usize> {
+    if self.compression == Compression::None {
+      return (!entries.is_empty()).then_some(1);
+    }
+    let mut bytes = 0;
+    for (i, entry) in entries.iter().enumerate() {
+      let size = bincode::serialized_size(entry).unwrap_or(MAX_ENTRY_BYTES);
+      if i > 0 && bytes + size > MAX_ENTRY_BYTES {
+        return Some(i);
+      }
+      bytes += size;
+      if i + 1 == BLOCK_ENTRIES || bytes >= BLOCK_BYTES {
+        return Some(i + 1);
+      }
+    }
+    None
+  }
+  /// Record payload of a block
+  pub(crate) fn encode(&self, entries: &[Entry]) -> crate::Result<Vec<u8>> {
+    let payload = self.pack(entries)?;
+    Ok(match &self.sealer {
+      Some(sealer) => sealer.seal(&payload),
+      None => payload,
+    })
+  }
+  /// Entries of a record payload
+  pub(crate) fn decode(&self, payload: &[u8]) -> crate::Result<Vec<Entry>> {
+    let payload = self.body(payload)?;
+    match &self.sealer {
+      Some(sealer) => self.unpack(&sealer.open(payload)?),
+      None => self.unpack(payload),
+    }
+  }
+  /// Number of entries in a record payload
+  pub(crate) fn count(&self, payload: &[u8]) -> crate::Result<usize> {
+    if self.compression == Compression::None {
+      return Ok(1);
+    }
+    let payload = self.body(payload)?;
+    let opened;
+    let payload = match &self.sealer {
+      Some(sealer) => {
+        opened = sealer.open(payload)?;
+        &opened
+      }
+      None => payload,
+    };
+    payload
+      .get(..4)
+      .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]) as usize)
+      .ok_or_else(|| Error::Decompress("block too short".to_string()))
+  }
+  /// Payload without the digest of chained files
+  fn body<'a>(&self, payload: &'a [u8]) -> crate::Result<&'a [u8]> {
+    match self.chained {
+      true => Ok(chain::split(payload)?.1),
+      false => Ok(payload),
+    }
+  }
+  fn pack(&self, entries: &[Entry]) -> crate::Result<Vec<u8>> {
+    if self.compression == Compression::None {
+      return bincode::serialize(&entries[0]).map_err(Error::Encode);
+    }
+    let entries = entries
+      .iter()
+      .map(bincode::serialize)
+      .collect::<bincode::Result<Vec<_>>>()
+      .map_err(Error::Encode)?;
+    let raw = bincode::serialize(&entries).map_err(Error::Encode)?;
+    let mut payload = (entries.len() as u32).to_le_bytes().to_vec();
+    match self.compression {
+      Compression::Zstd => payload.extend(zstd::bulk::compress(&raw, ZSTD_LEVEL)?),
+      Compression::Lz4 => payload.extend(lz4_flex::compress_prepend_size(&raw)),
+      Compression::None => unreachable!(),
+    }
+    Ok(payload)
+  }
+  fn unpack(&self, payload: &[u8]) -> crate::Result<Vec<Entry>> {
+    if self.compression == Compression::None {
+      return Ok(vec![self.entry(payload.to_vec())?]);
+    }
+    let data = payload.get(4..).unwrap_or_default();
+    let raw = match self.compression {
+      Compression::Zstd => {
+        zstd::stream::decode_all(data).map_err(|e| Error::Decompress(e.to_string()))?
+      }
+      Compression::Lz4 => {
+        lz4_flex::decompress_size_prepended(data).map_err(|e| Error::Decompress(e.to_string()))?
+      }
+      Compression::None => unreachable!(),
+    };
+    if self.legacy_entries {
+      let entries: Vec<EntryV1> = bincode::deserialize(&raw)?;
+      return Ok(entries.into_iter().map(Entry::from).collect());
+    }
+    let entries: Vec<Vec<u8>> = bincode::deserialize(&raw)?;
+    entries.into_iter().map(|e| self.entry(e)).collect()
+  }
+  fn entry(&self, bytes: Vec<u8>) -> crate::Result<Entry> {
+    if self.legacy_entries {
+      return Ok(bincode::deserialize::<EntryV1>(&bytes)?.into());
+    }
+    decode_padded(bytes)
+  }
+}
@@ -0,0 +1,127 @@
+//! Bloom filters over indexed entry fields
+//!
+//! The sparse index keeps a filter of the sender, unit and severity
+//! of the entries of every segment, so reads for e.g. a single host
+//! skip the segments that cannot hold its entries. Filters are built
+//! with a fixed FNV-1a based hash, so they stay valid across releases.
+//! The filters are stored in plain text, so encrypted files get full
+//! filters instead, which reveal nothing and never skip a segment.
+use super::{Entry, Severity};
+use serde::{Deserialize, Serialize};
+
+/// Size of a filter in bits, about half a byte per entry of a segment
+const BITS: u64 = 4096;
+/// Bits set per key
+const HASHES: u64 = 4;
+
+/// Sender, unit and severity values an entry must have
+///
+/// Each non-empty list must contain the value of the entry,
+/// empty lists match every entry.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeyFilter {
+  pub senders: Vec<String>,
+  pub units: Vec<String>,
+  pub severities: Vec<Severity>,
+}
+
+impl KeyFilter {
+  /// True if the filter matches every entry
+  pub fn is_empty(&self) -> bool {
+    self.senders.is_empty() && self.units.is_empty() && self.severities.is_empty()
+  }
+  pub fn matches(&self, entry: &Entry) -> bool {
+    let fields = entry.fields.as_ref();
+    let unit = fields.and_then(|f| f.unit.as_ref());
+    let severity = fields.and_then(|f| f.severity);
+    (self.senders.is_empty() || self.senders.contains(&entry.sender))
+      && (self.units.is_empty() || unit.is_some_and(|unit| self.units.contains(unit)))
+      && (self.severities.is_empty() || severity.is_some_and(|s| self.severities.contains(&s)))
+  }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct Bloom {
+  bits: Vec<u64>,
+}
+
+impl Bloom {
+  pub(crate) fn new() -> Self {
+    Self {
+      bits: vec![0; (BITS / 64) as usize],
+    }
+  }
+  /// Filter that matches everything
+  pub(crate) fn full() -> Self {
+    Self {
+      bits: vec![u64::MAX; (BITS / 64) as usize],
+    }
+  }
+  pub(crate) fn add_entry(&mut self, entry: &Entry) {
+    self.insert(&sender_key(&entry.sender));
+    if let Some(fields) = &entry.fields {
+      if let Some(unit) = &fields.unit {
+        self.insert(&unit_key(unit));
+      }
+      if let Some(severity) = fields.severity {
+        self.insert(&severity_key(severity));
+      }
+    }
+  }
+  /// False if no entry of the segment can match `keys`
+  pub(crate) fn may_match(&self, keys: &KeyFilter) -> bool {
+    let senders =
+      keys.senders.is_empty() || keys.senders.iter().any(|s| self.contains(&sender_key(s)));
+    let units = keys.units.is_empty() || keys.units.iter().any(|u| self.contains(&unit_key(u)));
+    let severities = keys.severities.is_empty()
+      || keys
+        .severities
+        .iter()
+        .any(|s| self.contains(&severity_key(*s)));
+    senders && units && severities
+  }
+  fn insert(&mut self, key: &[u8]) {
+    for bit in positions(key) {
+      self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+    }
+  }
+  fn contains(&self, key: &[u8]) -> bool {
+    positions(key).all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+  }
+}
+
+fn sender_key(sender: &str) -> Vec<u8> {
+  [b"s:", sender.as_bytes()].concat()
+}
+
+fn unit_key(unit: &str) -> Vec<u8> {
+  [b"u:", unit.as_bytes()].concat()
+}
+
+fn severity_key(severity: Severity) -> Vec<u8> {
+  vec![b'v', b':', severity.code() as u8]
+}
+
+/// Bit positions of a key, by double hashing
+fn positions(key: &[u8]) -> impl Iterator<Item = u64> {
+  let hash = mix(fnv1a(key));
+  let h1 = hash & 0xffff_ffff;
+  let h2 = (hash >> 32) | 1;
+  (0..HASHES).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % BITS)
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+  bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+    (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+  })
+}
+
+/// Spread the bits of a hash, FNV-1a alone leaves the high bits
+/// of short keys poorly mixed
+fn mix(mut x: u64) -> u64 {
+  x ^= x >> 30;
+  x = x.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+  x ^= x >> 27;
+  x = x.wrapping_mul(0x94d0_49bb_1331_11eb);
+  x ^ (x >> 31)
+}
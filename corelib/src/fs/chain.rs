@@ -0,0 +1,133 @@
+//! Tamper evident hash chain over data records
+//!
+//! In chained files every record payload starts with a SHA-256 digest
+//! of the previous record's digest and the rest of the payload, so
+//! editing, removing or reordering a record breaks the chain from that
+//! record on. The first record links to an all zero digest, and the
+//! digest of the last record is stored in the index on close.
+use super::record::{Frame, RecordReader};
+use super::{KeyRing, LogFile};
+use crate::Error;
+use sha2::{Digest as _, Sha256};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// SHA-256 digest of a chain link
+pub type Digest = [u8; 32];
+
+/// Digest the first record links to
+pub(crate) const GENESIS: Digest = [0; 32];
+
+/// Digest of a record with the given body, following `prev`
+pub(crate) fn link(prev: &Digest, body: &[u8]) -> Digest {
+  let mut hasher = Sha256::new();
+  hasher.update(prev);
+  hasher.update(body);
+  hasher.finalize().into()
+}
+
+/// Split a chained record payload into its digest and body
+pub(crate) fn split(payload: &[u8]) -> crate::Result<(Digest, &[u8])> {
+  if payload.len() < 32 {
+    return Err(Error::Decode(Box::new(bincode::ErrorKind::Custom(
+      "record too short for its digest".to_string(),
+    ))));
+  }
+  let (digest, body) = payload.split_at(32);
+  let mut res = GENESIS;
+  res.copy_from_slice(digest);
+  Ok((res, body))
+}
+
+/// Result of verifying the hash chain of a file
+#[derive(Debug, Clone)]
+pub struct Verification {
+  /// Number of entries before the break, or all entries
+  pub entries: usize,
+  /// Digest of the last intact record
+  pub digest: Digest,
+  /// First place where the chain breaks
+  pub broken: Option<ChainBreak>,
+}
+
+impl Verification {
+  /// True if the whole file is covered by an intact chain
+  pub fn is_intact(&self) -> bool {
+    self.broken.is_none()
+  }
+}
+
+/// Position where a hash chain breaks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainBreak {
+  /// Position of the first entry of the broken record (0 based).
+  /// Equals the entry count when only the digest in the index differs.
+  pub entry: usize,
+  /// Byte offset of the broken record
+  pub offset: u64,
+}
+
+/// Verify the hash chain of a towl file
+pub fn verify<P: AsRef<Path>>(path: P) -> crate::Result<Verification> {
+  verify_with(path, &KeyRing::default())
+}
+
+/// Verify the hash chain of a towl file, taking the key of
+/// an encrypted file from `keys`
+///
+/// Every record is checked against its predecessor; for a closed
+/// file the last digest must also match the one in the index.
+pub fn verify_with<P: AsRef<Path>>(path: P, keys: &KeyRing) -> crate::Result<Verification> {
+  verify_log(&LogFile::open_readonly_with(path, keys)?)
+}
+
+/// Verify the hash chain of an opened file, see `verify_with`
+pub(crate) fn verify_log(log: &LogFile) -> crate::Result<Verification> {
+  if !log.header.hash_chain {
+    return Err(Error::NoHashChain);
+  }
+
+  let end = log.data_end()?;
+  let mut file = File::open(&log.path)?;
+  file.seek(SeekFrom::Start(log.data_start))?;
+  let reader = BufReader::new(file.take(end - log.data_start));
+  let mut reader = RecordReader::with_framing(reader, log.data_start, log.codec.framing);
+
+  let mut res = Verification {
+    entries: 0,
+    digest: GENESIS,
+    broken: None,
+  };
+  while let Some(frame) = reader.next_frame()? {
+    let (offset, payload) = match frame {
+      Frame::Record { offset, payload } => (offset, payload),
+      Frame::Damaged { offset } | Frame::Torn { offset } => {
+        res.broken = Some(ChainBreak {
+          entry: res.entries,
+          offset,
+        });
+        return Ok(res);
+      }
+    };
+    let (digest, body) = split(&payload)?;
+    if link(&res.digest, body) != digest {
+      res.broken = Some(ChainBreak {
+        entry: res.entries,
+        offset,
+      });
+      return Ok(res);
+    }
+    res.digest = digest;
+    res.entries += log.codec.count(&payload)?;
+  }
+
+  // Records cut off at the end only show up in the closed index
+  if log.index.closed.is_some() && log.index.digest != Some(res.digest) {
+    res.broken = Some(ChainBreak {
+      entry: res.entries,
+      offset: end,
+    });
+  }
+  Ok(res)
+}
@@ -0,0 +1,206 @@
+//! Encryption at rest
+//!
+//! Every data record payload (a single entry or a compressed block)
+//! is sealed with an AEAD cipher, stored as
+//!
+//! | bytes | content |
+//! | --- | --- |
+//! | 12 | random nonce |
+//! | rest | ciphertext and 16 byte tag |
+//!
+//! The header stores the cipher, the key id and a sealed check value,
+//! so a wrong key is detected when the file is opened. Keys are kept
+//! outside of towl files, in a local key file with one key per line:
+//!
+//! ```text
+//! # key id    hex encoded 32 byte key
+//! archive-2023 7f0c...e1
+//! ```
+use crate::Error;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::Aes256Gcm;
+use chacha20poly1305::ChaCha20Poly1305;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+
+/// Size of the nonce prefix
+const NONCE_LEN: usize = 12;
+/// Plaintext sealed into the header to check the key
+const CHECK_TEXT: &[u8] = b"towl key check";
+
+/// AEAD cipher of an encrypted file
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cipher {
+  Aes256Gcm,
+  ChaCha20Poly1305,
+}
+
+/// 256 bit encryption key with its id
+#[derive(Clone)]
+pub struct Key {
+  id: String,
+  bytes: [u8; 32],
+}
+
+impl Key {
+  pub fn new(id: String, bytes: [u8; 32]) -> Self {
+    Self { id, bytes }
+  }
+  pub fn id(&self) -> &str {
+    &self.id
+  }
+}
+
+// Never print key material
+impl fmt::Debug for Key {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Key").field("id", &self.id).finish()
+  }
+}
+
+/// Keys available to open encrypted files
+#[derive(Clone, Debug, Default)]
+pub struct KeyRing {
+  keys: Vec<Key>,
+}
+
+impl KeyRing {
+  /// Load keys from a key file
+  pub fn load<P: AsRef<Path>>(path: P) -> crate::Result<Self> {
+    let text = std::fs::read_to_string(path)?;
+    let mut res = KeyRing::default();
+    for (i, line) in text.lines().enumerate() {
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+      let key = line
+        .split_once(char::is_whitespace)
+        .and_then(|(id, hex)| Some(Key::new(id.to_string(), parse_hex(hex.trim())?)))
+        .ok_or(Error::KeyFile { line: i + 1 })?;
+      res.add(key);
+    }
+    Ok(res)
+  }
+  /// Add a key, replacing any key with the same id
+  pub fn add(&mut self, key: Key) {
+    self.keys.retain(|k| k.id != key.id);
+    self.keys.push(key);
+  }
+  pub fn get(&self, id: &str) -> Option<&Key> {
+    self.keys.iter().find(|k| k.id == id)
+  }
+}
+
+impl From<Key> for KeyRing {
+  fn from(key: Key) -> Self {
+    KeyRing { keys: vec![key] }
+  }
+}
+
+/// Encryption of a new log file
+#[derive(Clone, Debug)]
+pub struct Encryption {
+  pub cipher: Cipher,
+  pub key: Key,
+}
+
+/// Encryption settings stored in the header
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct KeyInfo {
+  pub cipher: Cipher,
+  pub key_id: String,
+  /// `CHECK_TEXT` sealed with the key
+  check: Vec<u8>,
+}
+
+impl KeyInfo {
+  pub(crate) fn new(encryption: &Encryption) -> Self {
+    let sealer = Sealer::new(encryption.cipher, &encryption.key);
+    Self {
+      cipher: encryption.cipher,
+      key_id: encryption.key.id.clone(),
+      check: sealer.seal(CHECK_TEXT),
+    }
+  }
+  /// Find and check the key of the file
+  pub(crate) fn unlock(&self, keys: &KeyRing) -> crate::Result<Sealer> {
+    let key = keys
+      .get(&self.key_id)
+      .ok_or_else(|| Error::MissingKey(self.key_id.clone()))?;
+    let sealer = Sealer::new(self.cipher, key);
+    match sealer.open(&self.check) {
+      Ok(text) if text == CHECK_TEXT => Ok(sealer),
+      _ => Err(Error::WrongKey(self.key_id.clone())),
+    }
+  }
+}
+
+/// Seals and opens record payloads
+#[derive(Clone)]
+pub(crate) enum Sealer {
+  Aes(Box<Aes256Gcm>),
+  ChaCha(Box<ChaCha20Poly1305>),
+}
+
+impl Sealer {
+  fn new(cipher: Cipher, key: &Key) -> Self {
+    match cipher {
+      Cipher::Aes256Gcm => Sealer::Aes(Box::new(Aes256Gcm::new(&key.bytes.into()))),
+      Cipher::ChaCha20Poly1305 => {
+        Sealer::ChaCha(Box::new(ChaCha20Poly1305::new(&key.bytes.into())))
+      }
+    }
+  }
+  /// Encrypt with a random nonce, prepended to the ciphertext
+  pub(crate) fn seal(&self, plain: &[u8]) -> Vec<u8> {
+    let (nonce, sealed) = match self {
+      Sealer::Aes(c) => {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        (nonce, c.encrypt(&nonce, plain))
+      }
+      Sealer::ChaCha(c) => {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        (nonce, c.encrypt(&nonce, plain))
+      }
+    };
+    // Only fails for inputs far above the record size limit
+    let sealed = sealed.expect("payload too large to encrypt");
+    let mut payload = nonce.to_vec();
+    payload.extend(sealed);
+    payload
+  }
+  /// Decrypt and authenticate a sealed payload
+  pub(crate) fn open(&self, payload: &[u8]) -> crate::Result<Vec<u8>> {
+    if payload.len() < NONCE_LEN {
+      return Err(Error::Decrypt);
+    }
+    let (nonce, sealed) = payload.split_at(NONCE_LEN);
+    let plain = match self {
+      Sealer::Aes(c) => c.decrypt(nonce.into(), sealed),
+      Sealer::ChaCha(c) => c.decrypt(nonce.into(), sealed),
+    };
+    plain.map_err(|_| Error::Decrypt)
+  }
+}
+
+impl fmt::Debug for Sealer {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Sealer::Aes(_) => write!(f, "Sealer(Aes256Gcm)"),
+      Sealer::ChaCha(_) => write!(f, "Sealer(ChaCha20Poly1305)"),
+    }
+  }
+}
+
+fn parse_hex(hex: &str) -> Option<[u8; 32]> {
+  if hex.len() != 64 || !hex.is_ascii() {
+    return None;
+  }
+  let mut bytes = [0u8; 32];
+  for (i, byte) in bytes.iter_mut().enumerate() {
+    *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+  }
+  Some(bytes)
+}
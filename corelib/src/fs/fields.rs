@@ -0,0 +1,141 @@
+//! Structured part of log entries
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+/// Syslog severity levels, RFC 5424 codes 0 - 7
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+  Emergency,
+  Alert,
+  Critical,
+  Error,
+  Warning,
+  Notice,
+  Info,
+  Debug,
+}
+
+impl Severity {
+  /// All severities, ordered by their code
+  pub const ALL: [Severity; 8] = [
+    Severity::Emergency,
+    Severity::Alert,
+    Severity::Critical,
+    Severity::Error,
+    Severity::Warning,
+    Severity::Notice,
+    Severity::Info,
+    Severity::Debug,
+  ];
+  /// Syslog code of the severity
+  pub fn code(self) -> i32 {
+    self as i32
+  }
+  /// Severity of a syslog code
+  pub fn from_code(code: i64) -> Option<Self> {
+    usize::try_from(code)
+      .ok()
+      .and_then(|i| Self::ALL.get(i).copied())
+  }
+  /// Severity of a level name used by common loggers, e.g. `warn`,
+  /// or of a syslog code given as text
+  pub fn from_name(name: &str) -> Option<Self> {
+    if let Ok(code) = name.trim().parse::<i64>() {
+      return Self::from_code(code);
+    }
+    let severity = match name.trim().to_ascii_lowercase().as_str() {
+      "emerg" | "emergency" | "panic" => Severity::Emergency,
+      "alert" => Severity::Alert,
+      "crit" | "critical" | "fatal" => Severity::Critical,
+      "err" | "error" => Severity::Error,
+      "warn" | "warning" => Severity::Warning,
+      "notice" => Severity::Notice,
+      "info" | "informational" => Severity::Info,
+      "debug" | "trace" => Severity::Debug,
+      _ => return None,
+    };
+    Some(severity)
+  }
+}
+
+/// Structured fields of an entry
+///
+/// Stored next to the raw `log_entry`, so queries do not have to
+/// parse it again. Every field is optional; anything that has no
+/// field of its own goes into `extra`.
+///
+/// Encoded inside `Entry` before its `seq`: adding a field changes
+/// the entry layout and needs a new format version.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Fields {
+  pub severity: Option<Severity>,
+  pub hostname: Option<String>,
+  /// Systemd unit or application name
+  pub unit: Option<String>,
+  pub pid: Option<u32>,
+  /// Log message without metadata
+  pub message: Option<String>,
+  /// Other key/value pairs
+  pub extra: BTreeMap<String, String>,
+  /// Event time at the source, e.g. journald `__REALTIME_TIMESTAMP`
+  pub timestamp: Option<DateTime<Utc>>,
+}
+
+impl Fields {
+  /// Value of a field by name: `severity` (its code), `hostname`,
+  /// `unit`, `pid`, `message`, or a key of `extra`
+  pub fn get(&self, name: &str) -> Option<Cow<'_, str>> {
+    match name {
+      "severity" => self.severity.map(|s| Cow::Owned(s.code().to_string())),
+      "hostname" => self.hostname.as_deref().map(Cow::Borrowed),
+      "unit" => self.unit.as_deref().map(Cow::Borrowed),
+      "pid" => self.pid.map(|pid| Cow::Owned(pid.to_string())),
+      "message" => self.message.as_deref().map(Cow::Borrowed),
+      _ => self.extra.get(name).map(|v| Cow::Borrowed(v.as_str())),
+    }
+  }
+}
+
+/// Fields sent over gRPC
+///
+/// The event time is sent as RFC 3339 text.
+#[cfg(feature = "proto")]
+impl From<Fields> for proto::towl::Fields {
+  fn from(fields: Fields) -> Self {
+    proto::towl::Fields {
+      severity: fields.severity.map(Severity::code),
+      hostname: fields.hostname,
+      unit: fields.unit,
+      pid: fields.pid,
+      message: fields.message,
+      extra: fields.extra.into_iter().collect(),
+      timestamp_rfc3339: fields.timestamp.map(|t| t.to_rfc3339()),
+    }
+  }
+}
+
+/// Fields received over gRPC
+///
+/// Unknown severity codes and invalid event times are left unset,
+/// like values a parser cannot read.
+#[cfg(feature = "proto")]
+impl From<proto::towl::Fields> for Fields {
+  fn from(fields: proto::towl::Fields) -> Self {
+    Fields {
+      severity: fields
+        .severity
+        .and_then(|code| Severity::from_code(code.into())),
+      hostname: fields.hostname,
+      unit: fields.unit,
+      pid: fields.pid,
+      message: fields.message,
+      extra: fields.extra.into_iter().collect(),
+      timestamp: fields
+        .timestamp_rfc3339
+        .and_then(|t| DateTime::parse_from_rfc3339(&t).ok())
+        .map(|t| t.with_timezone(&Utc)),
+    }
+  }
+}
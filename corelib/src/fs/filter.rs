@@ -0,0 +1,141 @@
+//! Entry filters evaluated inside reads
+//!
+//! A `Filter` is checked on the blocking thread that decodes the
+//! entries, so only matching entries are sent over channels. The
+//! parts that indexes can answer - time range, sequence number,
+//! senders, units and severities, full text terms - are also used
+//! to skip whole segments or records before decoding them.
+use super::{Entry, KeyFilter, Query, Severity, TimeRange};
+use regex::Regex;
+
+/// Pattern on the raw log text
+#[derive(Clone, Debug)]
+pub enum TextMatch {
+  /// Plain substring, case sensitive
+  Contains(String),
+  Regex(Regex),
+}
+
+impl TextMatch {
+  pub fn matches(&self, text: &str) -> bool {
+    match self {
+      TextMatch::Contains(s) => text.contains(s.as_str()),
+      TextMatch::Regex(re) => re.is_match(text),
+    }
+  }
+}
+
+/// Conditions an entry must meet, all of them
+///
+/// Empty lists and unset options match every entry.
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+  /// Time range, by received or source time
+  pub range: TimeRange,
+  /// Senders, units and severities, checked against the
+  /// bloom filters of segments as well
+  pub keys: KeyFilter,
+  /// Log format codes
+  pub log_formats: Vec<i32>,
+  /// Only entries at least this severe, e.g. `Warning`
+  /// matches warnings, errors and worse
+  pub min_severity: Option<Severity>,
+  /// Substring or regex on the raw log text
+  pub text: Option<TextMatch>,
+  /// Full text query, answered with the full text index if there is one
+  pub query: Option<Query>,
+  /// Structured field values by field name, see `Fields::get`
+  pub fields: Vec<(String, String)>,
+  /// Only entries with a sequence number above this one
+  pub after_seq: Option<u64>,
+}
+
+impl Filter {
+  /// Filter matching every entry
+  pub fn new() -> Self {
+    Self::default()
+  }
+  pub fn range(mut self, range: TimeRange) -> Self {
+    self.range = range;
+    self
+  }
+  pub fn sender(mut self, sender: &str) -> Self {
+    self.keys.senders.push(sender.to_string());
+    self
+  }
+  pub fn log_format(mut self, log_format: i32) -> Self {
+    self.log_formats.push(log_format);
+    self
+  }
+  pub fn min_severity(mut self, severity: Severity) -> Self {
+    self.min_severity = Some(severity);
+    self
+  }
+  pub fn contains(mut self, text: &str) -> Self {
+    self.text = Some(TextMatch::Contains(text.to_string()));
+    self
+  }
+  /// Match the raw log text against a regular expression
+  pub fn regex(mut self, pattern: &str) -> Result<Self, regex::Error> {
+    self.text = Some(TextMatch::Regex(Regex::new(pattern)?));
+    Ok(self)
+  }
+  pub fn query(mut self, query: Query) -> Self {
+    self.query = Some(query);
+    self
+  }
+  /// Require a structured field value, e.g. `("unit", "nginx.service")`
+  pub fn field(mut self, name: &str, value: &str) -> Self {
+    self.fields.push((name.to_string(), value.to_string()));
+    self
+  }
+  pub fn after_seq(mut self, seq: u64) -> Self {
+    self.after_seq = Some(seq);
+    self
+  }
+  pub fn matches(&self, entry: &Entry) -> bool {
+    let fields = entry.fields.as_ref();
+    self.range.contains(entry)
+      && self.after_seq.is_none_or(|seq| entry.seq > seq)
+      && self.keys.matches(entry)
+      && (self.log_formats.is_empty() || self.log_formats.contains(&entry.log_format))
+      && self.min_severity.is_none_or(|min| {
+        fields
+          .and_then(|f| f.severity)
+          .is_some_and(|severity| severity <= min)
+      })
+      && self.fields.iter().all(|(name, value)| {
+        fields
+          .and_then(|f| f.get(name))
+          .is_some_and(|v| v == value.as_str())
+      })
+      && self
+        .text
+        .as_ref()
+        .is_none_or(|text| text.matches(&entry.log_entry))
+      && self.query.as_ref().is_none_or(|query| query.matches(entry))
+  }
+  /// Keys the bloom filters of segments are checked for, including
+  /// the minimum severity and a required unit
+  pub(crate) fn block_keys(&self) -> KeyFilter {
+    let mut keys = self.keys.clone();
+    if let Some(min) = self.min_severity {
+      let severities: Vec<Severity> = Severity::ALL
+        .into_iter()
+        .filter(|s| *s <= min)
+        .filter(|s| keys.severities.is_empty() || keys.severities.contains(s))
+        .collect();
+      // An empty list would match everything, while no entry can match;
+      // the entries are rejected by `matches` then
+      if !severities.is_empty() {
+        keys.severities = severities;
+      }
+    }
+    for (name, value) in &self.fields {
+      if name == "unit" && keys.units.is_empty() {
+        keys.units.push(value.clone());
+      }
+    }
+    keys
+  }
+}
@@ -0,0 +1,204 @@
+//! Pull based readers over the data section
+use super::block::Codec;
+use super::record::{Frame, RecordReader};
+use super::{Entry, Filter};
+use crate::Error;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom, Take};
+
+type Reader = RecordReader<BufReader<Take<File>>>;
+
+/// Forward iterator over log entries
+///
+/// Reads through its own file handle, so it does not borrow the
+/// `LogFile`; entries appended after it was created are not visible.
+/// Damaged records are yielded as `Error::Corrupt` and skipped,
+/// iteration ends after a torn record or an I/O error. On a file
+/// that is not closed, a torn record at the end of the data is a
+/// write in progress and ends iteration without an error.
+pub struct Iter {
+  file: File,
+  codec: Codec,
+  /// File may still be written
+  live: bool,
+  reader: Option<Reader>,
+  /// Byte ranges still to read
+  ranges: VecDeque<(u64, u64)>,
+  /// Decoded entries of the current block
+  block: VecDeque<Entry>,
+  /// Entries to drop from the first block
+  skip: usize,
+  /// Only yield entries matching this filter
+  filter: Filter,
+}
+
+impl Iter {
+  pub(crate) fn new(
+    file: File,
+    codec: Codec,
+    live: bool,
+    ranges: Vec<(u64, u64)>,
+    filter: Filter,
+  ) -> Self {
+    Self {
+      file,
+      codec,
+      live,
+      reader: None,
+      ranges: ranges.into(),
+      block: VecDeque::new(),
+      skip: 0,
+      filter,
+    }
+  }
+  /// Drop the first `n` entries, used to start inside a block
+  pub(crate) fn skip_entries(mut self, n: usize) -> Self {
+    self.skip = n;
+    self
+  }
+  fn next_frame(&mut self) -> crate::Result<Option<Frame>> {
+    loop {
+      if let Some(reader) = &mut self.reader {
+        if let Some(frame) = reader.next_frame()? {
+          return Ok(Some(frame));
+        }
+      }
+      // Current range is done, move on to the next one
+      let (start, end) = match self.ranges.pop_front() {
+        Some(range) => range,
+        None => return Ok(None),
+      };
+      self.reader = Some(open_range(&self.file, &self.codec, start, end)?);
+    }
+  }
+  fn matches(&self, entry: &Entry) -> bool {
+    self.filter.matches(entry)
+  }
+}
+
+impl Iterator for Iter {
+  type Item = crate::Result<Entry>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    loop {
+      if let Some(entry) = self.block.pop_front() {
+        if self.matches(&entry) {
+          return Some(Ok(entry));
+        }
+        continue;
+      }
+      let frame = match self.next_frame() {
+        Ok(Some(frame)) => frame,
+        Ok(None) => return None,
+        Err(e) => {
+          // Do not retry a failing file
+          self.reader = None;
+          self.ranges.clear();
+          return Some(Err(e));
+        }
+      };
+      if let Frame::Torn { .. } = frame {
+        if self.live && self.ranges.is_empty() {
+          return None;
+        }
+      }
+      match decode(&self.codec, frame) {
+        Ok(block) => {
+          self.block = block.into();
+          let skip = std::mem::take(&mut self.skip).min(self.block.len());
+          self.block.drain(..skip);
+        }
+        Err(e) => return Some(Err(e)),
+      }
+    }
+  }
+}
+
+/// Backward iterator over log entries, newest first
+///
+/// Walks the sparse index segments from the end of the file,
+/// reading one segment (at most 1024 records) at a time. Records
+/// are only decoded when yielded, so taking the newest few entries
+/// costs a single segment read. Like `Iter`, a torn record at the
+/// end of a file that is not closed is skipped.
+pub struct RevIter {
+  file: File,
+  codec: Codec,
+  /// File may still be written and its last segment is not read yet
+  live: bool,
+  /// Segments still to read, the last one is read first
+  segments: Vec<(u64, u64)>,
+  /// Frames of the current segment, in file order
+  buffer: Vec<Frame>,
+  /// Decoded entries of the current block, in file order
+  block: Vec<Entry>,
+}
+
+impl RevIter {
+  pub(crate) fn new(file: File, codec: Codec, live: bool, segments: Vec<(u64, u64)>) -> Self {
+    Self {
+      file,
+      codec,
+      live,
+      segments,
+      buffer: Vec::new(),
+      block: Vec::new(),
+    }
+  }
+  fn load_segment(&mut self, start: u64, end: u64) -> crate::Result<()> {
+    let mut reader = open_range(&self.file, &self.codec, start, end)?;
+    while let Some(frame) = reader.next_frame()? {
+      self.buffer.push(frame);
+    }
+    // Segments are read from the end, only the first one read can
+    // end with a write in progress
+    if std::mem::take(&mut self.live) {
+      if let Some(Frame::Torn { .. }) = self.buffer.last() {
+        self.buffer.pop();
+      }
+    }
+    Ok(())
+  }
+}
+
+impl Iterator for RevIter {
+  type Item = crate::Result<Entry>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    loop {
+      if let Some(entry) = self.block.pop() {
+        return Some(Ok(entry));
+      }
+      if let Some(frame) = self.buffer.pop() {
+        match decode(&self.codec, frame) {
+          Ok(block) => self.block = block,
+          Err(e) => return Some(Err(e)),
+        }
+        continue;
+      }
+      let (start, end) = self.segments.pop()?;
+      if let Err(e) = self.load_segment(start, end) {
+        self.segments.clear();
+        self.buffer.clear();
+        return Some(Err(e));
+      }
+    }
+  }
+}
+
+/// Record reader over the bytes between `start` and `end`
+fn open_range(file: &File, codec: &Codec, start: u64, end: u64) -> crate::Result<Reader> {
+  let mut file = file.try_clone()?;
+  file.seek(SeekFrom::Start(start))?;
+  let reader = BufReader::new(file.take(end.saturating_sub(start)));
+  Ok(RecordReader::with_framing(reader, start, codec.framing))
+}
+
+/// Entries of a frame, one for uncompressed records
+fn decode(codec: &Codec, frame: Frame) -> crate::Result<Vec<Entry>> {
+  match frame {
+    Frame::Record { payload, .. } => codec.decode(&payload),
+    Frame::Damaged { offset } | Frame::Torn { offset } => Err(Error::Corrupt { offset }),
+  }
+}
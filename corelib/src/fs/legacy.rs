@@ -0,0 +1,62 @@
+//! Structures of previous towl versions
+//!
+//! Version 1 and 2 files store a bare bincode `Index` at byte 1024,
+//! and their data section starts at byte 2048. Version 1 files store
+//! bare bincode entries without framing. Entries before version 7
+//! have no structured fields.
+use super::{Entry, Index};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Start of the data section in version 1 and 2 files
+pub(crate) const DATA_START: u64 = 2048;
+
+/// Entry as stored by version 1 - 6
+#[derive(Serialize, Deserialize)]
+pub(crate) struct EntryV1 {
+  sender: String,
+  received: DateTime<Utc>,
+  log_format: i32,
+  log_entry: String,
+}
+
+impl From<EntryV1> for Entry {
+  fn from(e: EntryV1) -> Self {
+    Entry {
+      sender: e.sender,
+      received: e.received,
+      log_format: e.log_format,
+      log_entry: e.log_entry,
+      fields: None,
+      seq: 0,
+    }
+  }
+}
+
+/// Index as stored by version 1 and 2
+#[derive(Serialize, Deserialize)]
+pub(crate) struct IndexV1 {
+  opened: DateTime<Utc>,
+  closed: Option<DateTime<Utc>>,
+  count: usize,
+  first_date: Option<DateTime<Utc>>,
+  last_date: Option<DateTime<Utc>>,
+}
+
+impl From<IndexV1> for Index {
+  fn from(i: IndexV1) -> Self {
+    Index {
+      opened: i.opened,
+      closed: i.closed,
+      count: i.count,
+      first_date: i.first_date,
+      last_date: i.last_date,
+      digest: None,
+      first_source: None,
+      last_source: None,
+      first_seq: None,
+      last_seq: None,
+      stats: Default::default(),
+    }
+  }
+}
@@ -0,0 +1,181 @@
+//! Upgrade of towl files written by previous versions
+use super::{
+  chain, lock_error, lock_writer, read_header, read_index, sidecar_path, Encryption, Entry,
+  KeyRing, LogFile, Options, VERSION,
+};
+use crate::Error;
+use fs2::FileExt;
+use std::fs::File;
+use std::path::Path;
+
+/// Entries copied per append call
+const BATCH_SIZE: usize = 1024;
+
+/// Report of a migration
+#[derive(Debug, Clone)]
+pub struct Migration {
+  /// Version of the source file
+  pub from: i32,
+  /// Number of entries copied
+  pub entries: usize,
+  /// Offsets of damaged source records that were left out
+  pub damaged: Vec<u64>,
+}
+
+/// Rewrite a towl file of any supported version in the newest layout
+///
+/// The new file is written next to the original and renamed over it,
+/// so a crash leaves either the old or the new file behind. Fails with
+/// `Error::Locked` while any other handle has the file open. A file
+/// already in the newest layout is left untouched. A chained file
+/// with a broken chain is not migrated, `Error::Corrupt` points at
+/// the first broken record.
+pub fn migrate<P: AsRef<Path>>(path: P) -> crate::Result<Migration> {
+  migrate_with(path, &KeyRing::default())
+}
+
+/// Like `migrate`, with the keys of encrypted files
+///
+/// The new file is encrypted with the same cipher and key.
+pub fn migrate_with<P: AsRef<Path>>(path: P, keys: &KeyRing) -> crate::Result<Migration> {
+  let path = path.as_ref();
+
+  // Opened before locking, so a missing file leaves no lock sidecar
+  let mut file = File::open(path)?;
+
+  // No writer may append and no reader may hold
+  // the old file open while it is replaced
+  let _writer_lock = lock_writer(path)?;
+
+  // Checked before opening, so current files need no key
+  let header = read_header(&mut file)?;
+  if header.version == VERSION {
+    return Ok(Migration {
+      from: VERSION,
+      entries: read_index(&mut file, &header)?.count,
+      damaged: Vec::new(),
+    });
+  }
+  drop(file);
+
+  let source = LogFile::open_readonly_with(path, keys)?;
+  FileExt::try_lock_exclusive(source.file.get_ref()).map_err(|e| lock_error(e, path))?;
+
+  let tmp = sidecar_path(path, ".migrate");
+  remove_if_exists(&tmp)?;
+  let report = copy(&source, &tmp, keys)?;
+
+  // Replace the old file and its sidecars
+  std::fs::rename(&tmp, path)?;
+  std::fs::rename(sidecar_path(&tmp, ".sparse"), sidecar_path(path, ".sparse"))?;
+  remove_if_exists(&sidecar_path(&tmp, ".lock"))?;
+
+  Ok(report)
+}
+
+/// Write a copy of a towl file in the newest layout to `target`
+///
+/// The source file is only read, `target` must not exist yet.
+/// Encrypted files need their key, see `migrate_to_with`.
+pub fn migrate_to<P, Q>(path: P, target: Q) -> crate::Result<Migration>
+where
+  P: AsRef<Path>,
+  Q: AsRef<Path>,
+{
+  migrate_to_with(path, target, &KeyRing::default())
+}
+
+/// Like `migrate_to`, with the keys of encrypted files
+pub fn migrate_to_with<P, Q>(path: P, target: Q, keys: &KeyRing) -> crate::Result<Migration>
+where
+  P: AsRef<Path>,
+  Q: AsRef<Path>,
+{
+  let source = LogFile::open_readonly_with(path, keys)?;
+  copy(&source, target.as_ref(), keys)
+}
+
+/// Copy header, index dates and entries of `source` into a new file
+///
+/// A chained source must verify first, the copy gets a new chain
+/// that would hide any tampering with the old one.
+fn copy(source: &LogFile, target: &Path, keys: &KeyRing) -> crate::Result<Migration> {
+  let header = &source.header;
+  if header.hash_chain {
+    if let Some(broken) = chain::verify_log(source)?.broken {
+      return Err(Error::Corrupt {
+        offset: broken.offset,
+      });
+    }
+  }
+  // Encrypted files stay encrypted with their own key
+  let encryption = match &header.encryption {
+    Some(info) => Some(Encryption {
+      cipher: info.cipher,
+      key: keys
+        .get(&info.key_id)
+        .ok_or_else(|| Error::MissingKey(info.key_id.clone()))?
+        .clone(),
+    }),
+    None => None,
+  };
+  let mut log = LogFile::create(
+    target,
+    header.org.clone(),
+    header.title.clone(),
+    header.id,
+    Options {
+      compression: header.compression,
+      encryption,
+      hash_chain: header.hash_chain,
+    },
+  )?;
+
+  let mut report = Migration {
+    from: header.version,
+    entries: 0,
+    damaged: Vec::new(),
+  };
+  let mut batch: Vec<Entry> = Vec::with_capacity(BATCH_SIZE);
+  for entry in source.iter()? {
+    match entry {
+      Ok(entry) => batch.push(entry),
+      Err(Error::Corrupt { offset }) => report.damaged.push(offset),
+      Err(e) => return Err(e),
+    }
+    if batch.len() == BATCH_SIZE {
+      log.add_entries(&batch)?;
+      batch.clear();
+    }
+  }
+  log.add_entries(&batch)?;
+  // Writes the open block, so the index counts it
+  log.sync()?;
+  report.entries = log.index.count;
+
+  // Keep the original lifetime of the file
+  log.index.opened = source.index.opened;
+  log.index.closed = source.index.closed;
+  if log.index.closed.is_some() {
+    log.index.digest = log.chain;
+  }
+  log.save_index()?;
+  log.sync()?;
+
+  if !report.damaged.is_empty() {
+    log::warn!(
+      "{}: damaged records left out at offsets {:?}",
+      source.path.display(),
+      report.damaged
+    );
+  }
+
+  Ok(report)
+}
+
+fn remove_if_exists(path: &Path) -> crate::Result<()> {
+  match std::fs::remove_file(path) {
+    Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+    _ => Ok(()),
+  }
+}
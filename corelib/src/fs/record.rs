@@ -0,0 +1,167 @@
+//! Record framing of the data section
+//!
+//! Each record is stored as
+//!
+//! | bytes | content |
+//! | --- | --- |
+//! | 4 | payload length, u32 little endian |
+//! | 4 | CRC32 of the payload, u32 little endian |
+//! | length | payload |
+//!
+//! so readers can detect torn writes and flipped bits, and can
+//! step over a damaged record when its length is still intact.
+//! Version 1 files store bare bincode entries, those are read
+//! through the same reader in `Framing::Bare` mode.
+use super::legacy::EntryV1;
+use crate::Error;
+use std::io::{BufRead, ErrorKind, Read};
+
+/// Size of the length + checksum prefix
+pub(crate) const FRAME_HEADER_LEN: u64 = 8;
+/// Upper limit of a single record payload.
+/// A larger length can only come from a corrupt frame header.
+pub(crate) const MAX_PAYLOAD_LEN: u32 = 16 * 1024 * 1024;
+
+/// Build a framed record from the given payload
+pub(crate) fn frame(payload: &[u8]) -> Vec<u8> {
+  let mut buf = Vec::with_capacity(FRAME_HEADER_LEN as usize + payload.len());
+  buf.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+  buf.extend_from_slice(&crc32fast::hash(payload).to_le_bytes());
+  buf.extend_from_slice(payload);
+  buf
+}
+
+/// How records of the data section are stored
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Framing {
+  /// Bare bincode entries of version 1
+  Bare,
+  /// Length and CRC32 prefixed records
+  Framed,
+}
+
+/// One frame read from the data section
+pub(crate) enum Frame {
+  /// Valid record
+  Record { offset: u64, payload: Vec<u8> },
+  /// Complete frame with checksum mismatch,
+  /// reading can continue with the next frame
+  Damaged { offset: u64 },
+  /// Incomplete frame or impossible length, e.g. zero,
+  /// nothing can be read after it
+  Torn { offset: u64 },
+}
+
+/// Sequential frame reader that keeps track of byte offsets
+pub(crate) struct RecordReader<R> {
+  reader: R,
+  offset: u64,
+  framing: Framing,
+  done: bool,
+}
+
+impl<R: BufRead> RecordReader<R> {
+  /// Reader positioned at byte `offset` of the file
+  pub(crate) fn new(reader: R, offset: u64) -> Self {
+    Self::with_framing(reader, offset, Framing::Framed)
+  }
+  pub(crate) fn with_framing(reader: R, offset: u64, framing: Framing) -> Self {
+    Self {
+      reader,
+      offset,
+      framing,
+      done: false,
+    }
+  }
+  /// Byte offset of the next frame
+  pub(crate) fn offset(&self) -> u64 {
+    self.offset
+  }
+  /// Read next frame, `None` at the end of data
+  pub(crate) fn next_frame(&mut self) -> crate::Result<Option<Frame>> {
+    if self.done {
+      return Ok(None);
+    }
+    if self.framing == Framing::Bare {
+      return self.next_bare();
+    }
+    let offset = self.offset;
+
+    let mut head = [0u8; FRAME_HEADER_LEN as usize];
+    match read_full(&mut self.reader, &mut head)? {
+      0 => {
+        self.done = true;
+        return Ok(None);
+      }
+      n if n < head.len() => return Ok(Some(self.torn())),
+      _ => (),
+    }
+    let len = u32::from_le_bytes([head[0], head[1], head[2], head[3]]);
+    let crc = u32::from_le_bytes([head[4], head[5], head[6], head[7]]);
+
+    // Records are never empty; a zero length, with the zero checksum
+    // of empty input, is the zero filled tail a crash can leave behind
+    if len == 0 || len > MAX_PAYLOAD_LEN {
+      return Ok(Some(self.torn()));
+    }
+
+    let mut payload = Vec::with_capacity(len as usize);
+    (&mut self.reader)
+      .take(len as u64)
+      .read_to_end(&mut payload)?;
+
+    if payload.len() != len as usize {
+      return Ok(Some(self.torn()));
+    }
+
+    self.offset += FRAME_HEADER_LEN + len as u64;
+
+    if crc32fast::hash(&payload) != crc {
+      return Ok(Some(Frame::Damaged { offset }));
+    }
+
+    Ok(Some(Frame::Record { offset, payload }))
+  }
+  /// Read a bare version 1 entry as a record payload
+  ///
+  /// Bare entries carry no length or checksum, so any decode
+  /// error is treated as the torn end of data.
+  fn next_bare(&mut self) -> crate::Result<Option<Frame>> {
+    let offset = self.offset;
+    if self.reader.fill_buf()?.is_empty() {
+      self.done = true;
+      return Ok(None);
+    }
+    let entry: EntryV1 = match bincode::deserialize_from(&mut self.reader) {
+      Ok(entry) => entry,
+      Err(e) => match *e {
+        bincode::ErrorKind::Io(e) if e.kind() != ErrorKind::UnexpectedEof => return Err(e.into()),
+        _ => return Ok(Some(self.torn())),
+      },
+    };
+    self.offset += bincode::serialized_size(&entry).map_err(Error::Encode)?;
+    let payload = bincode::serialize(&entry).map_err(Error::Encode)?;
+    Ok(Some(Frame::Record { offset, payload }))
+  }
+  fn torn(&mut self) -> Frame {
+    self.done = true;
+    Frame::Torn {
+      offset: self.offset,
+    }
+  }
+}
+
+/// Read as many bytes as available into buf,
+/// returning the number of bytes read
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> crate::Result<usize> {
+  let mut read = 0;
+  while read < buf.len() {
+    match reader.read(&mut buf[read..]) {
+      Ok(0) => break,
+      Ok(n) => read += n,
+      Err(e) if e.kind() == ErrorKind::Interrupted => (),
+      Err(e) => return Err(e.into()),
+    }
+  }
+  Ok(read)
+}
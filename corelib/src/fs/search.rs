@@ -0,0 +1,250 @@
+//! Full text index
+//!
+//! When a file is closed, a `{file}.idx` sidecar is built that maps
+//! every token of the raw log text to the data records holding it,
+//! so searches only decode the records holding all query terms.
+//! Record numbers and offsets are stored as varint encoded deltas.
+//! The sidecar is sealed like the records of an encrypted file, and
+//! it can always be rebuilt from the data section.
+use super::block::Codec;
+use super::record::{self, Frame, RecordReader};
+use super::{Entry, LogFile};
+use crate::Error;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Longest indexed token in bytes, longer ones are only found by scanning
+const MAX_TOKEN_LEN: usize = 64;
+
+/// Search over the raw log text
+///
+/// Text is split into lowercase tokens at every character that is
+/// not a letter or digit, so `req-8F3A` matches the phrase `req 8f3a`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Query {
+  tokens: Vec<String>,
+  phrase: bool,
+}
+
+impl Query {
+  /// Entries holding every term of `text`, in any order
+  pub fn terms(text: &str) -> Self {
+    Self {
+      tokens: tokens(text).collect(),
+      phrase: false,
+    }
+  }
+  /// Entries holding the terms of `text` next to each other, in order
+  pub fn phrase(text: &str) -> Self {
+    Self {
+      tokens: tokens(text).collect(),
+      phrase: true,
+    }
+  }
+  pub fn matches(&self, entry: &Entry) -> bool {
+    if self.tokens.is_empty() {
+      return true;
+    }
+    let tokens: Vec<String> = tokens(&entry.log_entry).collect();
+    if self.phrase {
+      tokens
+        .windows(self.tokens.len())
+        .any(|window| window == self.tokens.as_slice())
+    } else {
+      self.tokens.iter().all(|token| tokens.contains(token))
+    }
+  }
+}
+
+/// Lowercase tokens of a text
+fn tokens(text: &str) -> impl Iterator<Item = String> + '_ {
+  text
+    .split(|c: char| !c.is_alphanumeric())
+    .filter(|token| !token.is_empty())
+    .map(str::to_lowercase)
+}
+
+/// Token to record index of a file
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct TextIndex {
+  /// Creation time of the indexed file, to detect a replaced file
+  opened: DateTime<Utc>,
+  /// Number of entries covered
+  count: usize,
+  /// Byte offset after the last covered record
+  pub end: u64,
+  /// Offsets of the covered records
+  records: Vec<u8>,
+  /// Numbers of the records holding each token
+  terms: BTreeMap<String, Vec<u8>>,
+}
+
+impl TextIndex {
+  /// Index every record of the data section
+  ///
+  /// Damaged records are left out, a torn tail ends the index.
+  pub(crate) fn build(log: &LogFile) -> crate::Result<Self> {
+    let end = log.data_end()?;
+    let mut file = File::open(&log.path)?;
+    file.seek(SeekFrom::Start(log.data_start))?;
+    let reader = BufReader::new(file.take(end - log.data_start));
+    let mut reader = RecordReader::with_framing(reader, log.data_start, log.codec.framing);
+
+    let mut count = 0;
+    let mut offsets = Vec::new();
+    let mut postings: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+    while let Some(frame) = reader.next_frame()? {
+      let (offset, payload) = match frame {
+        Frame::Record { offset, payload } => (offset, payload),
+        Frame::Damaged { .. } => continue,
+        Frame::Torn { .. } => break,
+      };
+      // Undecodable records are left out like damaged ones
+      let entries = match log.codec.decode(&payload) {
+        Ok(entries) => entries,
+        Err(_) => continue,
+      };
+      let n = offsets.len() as u64;
+      offsets.push(offset);
+      for entry in entries {
+        count += 1;
+        for token in tokens(&entry.log_entry).filter(|t| t.len() <= MAX_TOKEN_LEN) {
+          let records = postings.entry(token).or_default();
+          if records.last() != Some(&n) {
+            records.push(n);
+          }
+        }
+      }
+    }
+
+    Ok(Self {
+      opened: log.index.opened,
+      count,
+      end: reader.offset(),
+      records: encode(&offsets),
+      terms: postings
+        .into_iter()
+        .map(|(token, records)| (token, encode(&records)))
+        .collect(),
+    })
+  }
+  /// True if the index belongs to `log` and its entries are still there
+  pub(crate) fn covers(&self, log: &LogFile, end: u64) -> bool {
+    self.opened == log.index.opened && self.count <= log.index.count && self.end <= end
+  }
+  /// Byte ranges of the covered records that may match `query`
+  pub(crate) fn ranges(&self, query: &Query) -> Vec<(u64, u64)> {
+    let offsets = decode(&self.records);
+
+    // Records holding every indexed token of the query
+    let mut matching: Option<Vec<u64>> = None;
+    for token in query.tokens.iter().filter(|t| t.len() <= MAX_TOKEN_LEN) {
+      let records = self.terms.get(token).map(|r| decode(r)).unwrap_or_default();
+      matching = Some(match matching {
+        Some(matching) => intersect(&matching, &records),
+        None => records,
+      });
+    }
+    let matching = matching.unwrap_or_else(|| (0..offsets.len() as u64).collect());
+
+    let mut ranges: Vec<(u64, u64)> = Vec::new();
+    for n in matching {
+      let n = n as usize;
+      let start = offsets[n];
+      let end = offsets.get(n + 1).copied().unwrap_or(self.end);
+      match ranges.last_mut() {
+        // Merge with the previous record when they are adjacent
+        Some((_, last_end)) if *last_end == start => *last_end = end,
+        _ => ranges.push((start, end)),
+      }
+    }
+    ranges
+  }
+  /// Load index from its sidecar file
+  pub(crate) fn load(path: &Path, codec: &Codec) -> crate::Result<Self> {
+    let file = File::open(path)?;
+    let mut reader = RecordReader::new(BufReader::new(file), 0);
+    let payload = match reader.next_frame()? {
+      Some(Frame::Record { payload, .. }) => payload,
+      Some(Frame::Damaged { offset }) | Some(Frame::Torn { offset }) => {
+        return Err(Error::Corrupt { offset })
+      }
+      None => return Err(Error::Corrupt { offset: 0 }),
+    };
+    let payload = match &codec.sealer {
+      Some(sealer) => sealer.open(&payload)?,
+      None => payload,
+    };
+    Ok(bincode::deserialize(&payload)?)
+  }
+  /// Save index into its sidecar file, through a temp file
+  /// renamed over the old one
+  pub(crate) fn save(&self, path: &Path, codec: &Codec) -> crate::Result<()> {
+    let mut payload = bincode::serialize(self).map_err(Error::Encode)?;
+    if let Some(sealer) = &codec.sealer {
+      payload = sealer.seal(&payload);
+    }
+    let tmp = super::sidecar_path(path, ".tmp");
+    let mut file = File::create(&tmp)?;
+    file.write_all(&record::frame(&payload))?;
+    file.sync_data()?;
+    std::fs::rename(tmp, path)?;
+    Ok(())
+  }
+}
+
+/// Varint encoded deltas of increasing numbers
+fn encode(values: &[u64]) -> Vec<u8> {
+  let mut res = Vec::new();
+  let mut prev = 0;
+  for value in values {
+    let mut delta = value - prev;
+    prev = *value;
+    while delta >= 0x80 {
+      res.push(delta as u8 | 0x80);
+      delta >>= 7;
+    }
+    res.push(delta as u8);
+  }
+  res
+}
+
+fn decode(bytes: &[u8]) -> Vec<u64> {
+  let mut res = Vec::new();
+  let mut prev = 0;
+  let mut delta = 0;
+  let mut shift = 0;
+  for byte in bytes {
+    delta |= u64::from(byte & 0x7f) << shift;
+    shift += 7;
+    if byte & 0x80 == 0 {
+      prev += delta;
+      res.push(prev);
+      delta = 0;
+      shift = 0;
+    }
+  }
+  res
+}
+
+/// Numbers in both sorted lists
+fn intersect(a: &[u64], b: &[u64]) -> Vec<u64> {
+  let mut res = Vec::new();
+  let (mut i, mut j) = (0, 0);
+  while i < a.len() && j < b.len() {
+    match a[i].cmp(&b[j]) {
+      std::cmp::Ordering::Less => i += 1,
+      std::cmp::Ordering::Greater => j += 1,
+      std::cmp::Ordering::Equal => {
+        res.push(a[i]);
+        i += 1;
+        j += 1;
+      }
+    }
+  }
+  res
+}
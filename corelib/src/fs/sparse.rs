@@ -0,0 +1,294 @@
+//! Sparse offset index
+//!
+//! Stores the byte offset of every `SPARSE_EVERY`-th entry (or the
+//! block holding it), so readers can jump close to entry N and only
+//! walk the records after it.
+//! Each point also records the latest `received` time of its segment
+//! (the entries up to the next point), and a span of the earliest
+//! received and the earliest / latest source time, so time bounded
+//! reads can skip segments that cannot hold matching entries, and
+//! the highest sequence number, so resumed streams can skip the
+//! segments a client already has. A bloom filter of the senders,
+//! units and severities of a segment lets reads for given values
+//! skip it as well.
+//! The index is persisted as a CRC framed `{file}.sparse` sidecar;
+//! it can always be rebuilt from the data section by `reindex`.
+//! Bytes after the last covered entry, e.g. appended since a
+//! read-only handle loaded the sidecar, are read as an unindexed tail.
+use super::bloom::{Bloom, KeyFilter};
+use super::{record, Clock, Entry, TimeRange};
+use crate::Error;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::path::Path;
+
+/// Every n-th entry gets an index point
+pub(crate) const SPARSE_EVERY: usize = 1024;
+
+/// Position of an entry in the data section
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub(crate) struct Point {
+  /// Number of entries before this one
+  pub count: usize,
+  /// Byte offset of the record holding the entry
+  pub offset: u64,
+  /// Latest received time in the segment starting here
+  pub max_received: DateTime<Utc>,
+}
+
+/// Time span of a segment, next to its `Point`
+///
+/// Kept in a separate list at the end of the sidecar: sidecars
+/// written before it was added fail to decode and are rebuilt,
+/// while older readers ignore the extra bytes.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub(crate) struct Span {
+  pub min_received: DateTime<Utc>,
+  pub min_source: DateTime<Utc>,
+  pub max_source: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct SparseIndex {
+  every: usize,
+  /// Number of entries covered
+  count: usize,
+  /// Byte offset after the last covered entry
+  end: u64,
+  /// Received time of the last entry
+  last_received: Option<DateTime<Utc>>,
+  /// False once an entry was received before its predecessor,
+  /// e.g. because the server clock went backwards
+  monotonic: bool,
+  points: Vec<Point>,
+  /// Time span of each segment, by point
+  spans: Vec<Span>,
+  /// Highest sequence number of each segment, by point.
+  /// Trailing for the same reason as `spans`.
+  max_seqs: Vec<u64>,
+  /// Bloom filter of each segment, by point
+  blooms: Vec<Bloom>,
+}
+
+impl SparseIndex {
+  /// Empty index of a data section starting at `start`
+  pub(crate) fn new(start: u64) -> Self {
+    Self {
+      every: SPARSE_EVERY,
+      count: 0,
+      end: start,
+      last_received: None,
+      monotonic: true,
+      points: Vec::new(),
+      spans: Vec::new(),
+      max_seqs: Vec::new(),
+      blooms: Vec::new(),
+    }
+  }
+  /// Register the record stored at `offset` in `len` bytes,
+  /// holding the given entries
+  ///
+  /// Points are placed at record boundaries, so with compressed
+  /// blocks a segment holds at least `every` entries. Segments of
+  /// `encrypted` files get a full bloom filter, which reveals nothing
+  /// and is never ruled out.
+  pub(crate) fn add_record(&mut self, offset: u64, len: u64, entries: &[Entry], encrypted: bool) {
+    let mut new_point = self
+      .points
+      .last()
+      .is_none_or(|point| self.count - point.count >= self.every);
+
+    for entry in entries {
+      let received = entry.received;
+      let source = entry.time(Clock::Source);
+      if self.last_received.is_some_and(|last| received < last) {
+        self.monotonic = false;
+      }
+      self.last_received = Some(received);
+
+      match (
+        self.points.last_mut(),
+        self.spans.last_mut(),
+        self.max_seqs.last_mut(),
+        self.blooms.last_mut(),
+      ) {
+        (Some(point), Some(span), Some(max_seq), Some(bloom)) if !new_point => {
+          point.max_received = point.max_received.max(received);
+          span.min_received = span.min_received.min(received);
+          span.min_source = span.min_source.min(source);
+          span.max_source = span.max_source.max(source);
+          *max_seq = (*max_seq).max(entry.seq);
+          if !encrypted {
+            bloom.add_entry(entry);
+          }
+        }
+        _ => {
+          self.points.push(Point {
+            count: self.count,
+            offset,
+            max_received: received,
+          });
+          self.spans.push(Span {
+            min_received: received,
+            min_source: source,
+            max_source: source,
+          });
+          self.max_seqs.push(entry.seq);
+          let bloom = match encrypted {
+            true => Bloom::full(),
+            false => {
+              let mut bloom = Bloom::new();
+              bloom.add_entry(entry);
+              bloom
+            }
+          };
+          self.blooms.push(bloom);
+        }
+      }
+      new_point = false;
+      self.count += 1;
+    }
+    self.end = offset + len;
+  }
+  /// Number of entries covered
+  pub(crate) fn count(&self) -> usize {
+    self.count
+  }
+  /// Entry count and byte offset to start walking from to find entry `n`
+  pub(crate) fn lookup(&self, n: usize) -> (usize, u64) {
+    let i = self.points.partition_point(|p| p.count <= n);
+    match i.checked_sub(1) {
+      Some(i) => (self.points[i].count, self.points[i].offset),
+      // Empty index, walk from the start of data
+      None => (self.count, self.end),
+    }
+  }
+  /// Byte ranges of all segments, `end` is the end of data
+  pub(crate) fn segments(&self, end: u64) -> Vec<(u64, u64)> {
+    let mut segments: Vec<(u64, u64)> = (0..self.points.len())
+      .map(|i| (self.points[i].offset, self.segment_end(i)))
+      .collect();
+    segments.extend(self.tail(end));
+    segments
+  }
+  /// False if an entry was received before its predecessor
+  pub(crate) fn monotonic(&self) -> bool {
+    self.monotonic
+  }
+  /// Byte ranges of the segments that may hold entries in `range`
+  /// matching `keys`, `end` is the end of data
+  ///
+  /// Entries are appended in received order, so normally the
+  /// matching segments of a received time range are found by
+  /// binary search. If the clock went backwards, or the range is
+  /// on source time, we fall back to checking every segment.
+  /// Segments are skipped when their bloom filter rules out `keys`.
+  pub(crate) fn ranges_in(&self, range: &TimeRange, keys: &KeyFilter, end: u64) -> Vec<(u64, u64)> {
+    let mut first = 0;
+    let mut last = self.points.len();
+    if self.monotonic && range.clock == Clock::Received {
+      if let Some(after) = range.after {
+        first = self.points.partition_point(|p| p.max_received <= after);
+      }
+      if let Some(before) = range.before {
+        last = self.spans.partition_point(|s| s.min_received < before);
+      }
+    }
+
+    let matching = (first..last.max(first)).filter(|i| self.may_match(*i, range, keys));
+    self.merge(matching, end)
+  }
+  /// Byte ranges of every segment that may hold entries in `range`
+  /// matching `keys`, each with the earliest time of the range's
+  /// clock in it, and the unindexed tail with the earliest time there is
+  pub(crate) fn segments_in(
+    &self,
+    range: &TimeRange,
+    keys: &KeyFilter,
+    end: u64,
+  ) -> Vec<(DateTime<Utc>, (u64, u64))> {
+    let tail = self.tail(end).map(|tail| (DateTime::<Utc>::MIN_UTC, tail));
+    (0..self.points.len())
+      .filter(|i| self.may_match(*i, range, keys))
+      .map(|i| {
+        let span = &self.spans[i];
+        let first = match range.clock {
+          Clock::Received => span.min_received,
+          Clock::Source => span.min_source,
+        };
+        (first, (self.points[i].offset, self.segment_end(i)))
+      })
+      .chain(tail)
+      .collect()
+  }
+  /// Byte ranges of the segments that may hold entries with
+  /// a sequence number above `seq`, `end` is the end of data
+  pub(crate) fn ranges_after_seq(&self, seq: u64, end: u64) -> Vec<(u64, u64)> {
+    let matching = (0..self.points.len()).filter(|i| self.max_seqs[*i] > seq);
+    self.merge(matching, end)
+  }
+  /// Byte ranges of the given segments and the unindexed tail,
+  /// adjacent ones merged
+  fn merge<I>(&self, segments: I, end: u64) -> Vec<(u64, u64)>
+  where
+    I: Iterator<Item = usize>,
+  {
+    let segments = segments.map(|i| (self.points[i].offset, self.segment_end(i)));
+
+    let mut ranges: Vec<(u64, u64)> = Vec::new();
+    for (start, segment_end) in segments.chain(self.tail(end)) {
+      match ranges.last_mut() {
+        // Merge with the previous segment when they are adjacent
+        Some((_, last_end)) if *last_end == start => *last_end = segment_end,
+        _ => ranges.push((start, segment_end)),
+      }
+    }
+    ranges
+  }
+  /// True if segment `i` may hold entries in `range` matching `keys`
+  fn may_match(&self, i: usize, range: &TimeRange, keys: &KeyFilter) -> bool {
+    self.may_hold(i, range) && (keys.is_empty() || self.blooms[i].may_match(keys))
+  }
+  /// True if segment `i` may hold entries in `range`
+  fn may_hold(&self, i: usize, range: &TimeRange) -> bool {
+    let span = &self.spans[i];
+    match range.clock {
+      Clock::Received => range.overlaps(span.min_received, self.points[i].max_received),
+      Clock::Source => range.overlaps(span.min_source, span.max_source),
+    }
+  }
+  fn segment_end(&self, i: usize) -> u64 {
+    self.points.get(i + 1).map_or(self.end, |next| next.offset)
+  }
+  /// Unindexed bytes before `end`
+  fn tail(&self, end: u64) -> Option<(u64, u64)> {
+    (end > self.end).then_some((self.end, end))
+  }
+  /// Load index from its sidecar file
+  pub(crate) fn load(path: &Path) -> crate::Result<Self> {
+    let file = File::open(path)?;
+    let mut reader = record::RecordReader::new(BufReader::new(file), 0);
+    match reader.next_frame()? {
+      Some(record::Frame::Record { payload, .. }) => Ok(bincode::deserialize(&payload)?),
+      Some(record::Frame::Damaged { offset }) | Some(record::Frame::Torn { offset }) => {
+        Err(Error::Corrupt { offset })
+      }
+      None => Err(Error::Corrupt { offset: 0 }),
+    }
+  }
+  /// Save index into its sidecar file
+  ///
+  /// Written into a temp file first and renamed over the
+  /// old sidecar, so readers never see a half written index
+  pub(crate) fn save(&self, path: &Path) -> crate::Result<()> {
+    let payload = bincode::serialize(self).map_err(Error::Encode)?;
+    let tmp = super::sidecar_path(path, ".tmp");
+    let mut file = File::create(&tmp)?;
+    file.write_all(&record::frame(&payload))?;
+    file.sync_data()?;
+    std::fs::rename(tmp, path)?;
+    Ok(())
+  }
+}
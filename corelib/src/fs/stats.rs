@@ -0,0 +1,69 @@
+//! Per-file statistics, kept in the index
+use super::{Entry, Severity};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Senders counted one by one; entries of further senders, or of
+/// senders with a longer name, are only counted in `other_senders`,
+/// so the index stays within its region
+const MAX_SENDERS: usize = 128;
+const MAX_SENDER_LEN: usize = 128;
+/// Log formats counted one by one, see `other_log_formats`
+const MAX_LOG_FORMATS: usize = 64;
+
+/// Statistics of the entries stored in a file
+///
+/// Maintained on append, saved with the index and rebuilt by `reindex`.
+/// The index is not encrypted, so encrypted files count every entry
+/// in `other_senders` and keep no sender names.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+  /// Size of the data records in bytes, after compression
+  /// and encryption
+  pub bytes: u64,
+  /// Entries by sender
+  pub senders: BTreeMap<String, usize>,
+  /// Entries of senders not counted one by one, see `MAX_SENDERS`
+  pub other_senders: usize,
+  /// Entries by log format code
+  pub log_formats: BTreeMap<i32, usize>,
+  /// Entries of log formats above `MAX_LOG_FORMATS`
+  pub other_log_formats: usize,
+  /// Entries by severity, entries without one are not counted
+  pub severities: BTreeMap<Severity, usize>,
+  /// Smallest and largest serialized entry in bytes
+  pub min_entry_size: Option<u64>,
+  pub max_entry_size: Option<u64>,
+}
+
+impl Stats {
+  pub(crate) fn add_entry(&mut self, entry: &Entry, encrypted: bool) {
+    let senders = self.senders.len();
+    match self.senders.get_mut(&entry.sender) {
+      Some(count) => *count += 1,
+      None if !encrypted && senders < MAX_SENDERS && entry.sender.len() <= MAX_SENDER_LEN => {
+        self.senders.insert(entry.sender.clone(), 1);
+      }
+      None => self.other_senders += 1,
+    }
+    let log_formats = self.log_formats.len();
+    match self.log_formats.get_mut(&entry.log_format) {
+      Some(count) => *count += 1,
+      None if log_formats < MAX_LOG_FORMATS => {
+        self.log_formats.insert(entry.log_format, 1);
+      }
+      None => self.other_log_formats += 1,
+    }
+    if let Some(severity) = entry.fields.as_ref().and_then(|f| f.severity) {
+      *self.severities.entry(severity).or_default() += 1;
+    }
+    // Cannot fail for an in-memory size count
+    if let Ok(size) = bincode::serialized_size(entry) {
+      self.min_entry_size = Some(self.min_entry_size.map_or(size, |min| min.min(size)));
+      self.max_entry_size = Some(self.max_entry_size.map_or(size, |max| max.max(size)));
+    }
+  }
+  pub(crate) fn add_record(&mut self, len: u64) {
+    self.bytes += len;
+  }
+}
@@ -0,0 +1,63 @@
+//! Entry timestamps and time ranges
+//!
+//! Every entry has the time the server received it, and parsed
+//! entries may also carry the time of the event at its source, e.g.
+//! journald `__REALTIME_TIMESTAMP`. Buffered or replayed logs and
+//! hosts with a skewed clock make the two differ, so indexes and time
+//! bounded reads take the `Clock` to use.
+use super::Entry;
+use chrono::{DateTime, Utc};
+
+/// Which timestamp of an entry to use
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Clock {
+  /// Server side ingest time, `Entry.received`
+  #[default]
+  Received,
+  /// Event time at the source, `Fields.timestamp`. Entries without
+  /// one fall back to their received time.
+  Source,
+}
+
+/// Time range of entries, both ends exclusive and optional
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TimeRange {
+  pub clock: Clock,
+  /// Only entries after this time
+  pub after: Option<DateTime<Utc>>,
+  /// Only entries before this time
+  pub before: Option<DateTime<Utc>>,
+}
+
+impl TimeRange {
+  /// Unbounded range on the given clock
+  pub fn new(clock: Clock) -> Self {
+    Self {
+      clock,
+      after: None,
+      before: None,
+    }
+  }
+  pub fn after(mut self, after: DateTime<Utc>) -> Self {
+    self.after = Some(after);
+    self
+  }
+  pub fn before(mut self, before: DateTime<Utc>) -> Self {
+    self.before = Some(before);
+    self
+  }
+  /// True if the range has no bounds
+  pub fn is_unbounded(&self) -> bool {
+    self.after.is_none() && self.before.is_none()
+  }
+  pub fn contains(&self, entry: &Entry) -> bool {
+    self.contains_time(entry.time(self.clock))
+  }
+  pub fn contains_time(&self, time: DateTime<Utc>) -> bool {
+    self.after.is_none_or(|after| time > after) && self.before.is_none_or(|before| time < before)
+  }
+  /// True if entries between `min` and `max` may fall into the range
+  pub fn overlaps(&self, min: DateTime<Utc>, max: DateTime<Utc>) -> bool {
+    self.after.is_none_or(|after| max > after) && self.before.is_none_or(|before| min < before)
+  }
+}
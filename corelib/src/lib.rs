@@ -1,4 +1,9 @@
+pub mod error;
+pub mod format;
 pub mod fs;
 pub mod logger;
+pub mod store;
 
-pub type Result<T> = std::result::Result<T, String>;
+pub use error::Error;
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -1,5 +1,7 @@
+use crate::format::Registry;
 use crate::fs::{Entry, LogFile};
-use chrono::{Datelike, Timelike, Utc};
+use crate::store::LogStore;
+use chrono::{Timelike, Utc};
 use serde::{Deserialize, Serialize};
 use std::{path::Path, sync::Arc};
 use tokio::{
@@ -8,6 +10,13 @@ use tokio::{
   task::spawn_blocking,
 };
 
+/// Data directory of the log files, see `LogStore`
+pub const DATA_PATH: &str = "data";
+
+/// Entries added to the working file between two updates of its
+/// catalog entry, see `LogStore::update`
+pub const CATALOG_EVERY: usize = 1000;
+
 #[derive(Serialize, Deserialize, Clone)]
 struct Settings {
   file_max_count: i32,
@@ -25,17 +34,15 @@ impl Settings {
         .read(true)
         .write(true)
         .open(path)
-        .await
-        .expect("Cannot open settings file, but it exists")
-        .read_to_end(&mut buf);
+        .await?
+        .read_to_end(&mut buf)
+        .await?;
 
-      let settings: Settings = spawn_blocking(move || {
-        bincode::deserialize(&buf).expect("Error deser. settings from bytes")
-      })
-      .await
-      .unwrap();
+      let settings: Settings = spawn_blocking(move || bincode::deserialize(&buf))
+        .await
+        .unwrap()?;
 
-      return Ok(settings);
+      Ok(settings)
     } else {
       let mut settings = Settings {
         file_max_count,
@@ -50,66 +57,139 @@ impl Settings {
   async fn save(&mut self) -> crate::Result<()> {
     let s = self.clone();
 
-    let bytes = spawn_blocking(move || {
-      bincode::serialize(&s).expect("Error serializing settings to bincode bytes")
-    })
-    .await
-    .unwrap();
+    let bytes = spawn_blocking(move || bincode::serialize(&s))
+      .await
+      .unwrap()
+      .map_err(crate::Error::Encode)?;
 
     if !Path::new("settings").exists() {
-      tokio::fs::File::create("settings")
-        .await
-        .expect("Error creating settings file");
+      tokio::fs::File::create("settings").await?;
     }
 
     tokio::fs::OpenOptions::new()
       .write(true)
       .open("settings")
-      .await
-      .expect("Cannot open settings file, but it exists")
+      .await?
       .write_all(&bytes)
-      .await
-      .expect("Error writing settings to disk.");
+      .await?;
 
     Ok(())
   }
 }
 
-pub struct Logger {
+/// Working file with the store it belongs to
+///
+/// Only touched on blocking threads, file operations block.
+struct State {
   settings: Settings,
+  /// Catalog of working and archived files
+  store: LogStore,
   working: LogFile,
+  /// Sequence number of the next entry
+  next_seq: u64,
+  /// Entries added since the catalog was updated
+  unlisted: usize,
+}
+
+impl State {
+  /// Open the data directory and continue its working file,
+  /// or start the first one
+  fn open(settings: Settings, org: String, title: String) -> crate::Result<Self> {
+    let mut store = LogStore::open(DATA_PATH)?;
+
+    let working = match store.working().map(|info| info.id) {
+      Some(id) => {
+        let working = LogFile::open(store.path(id).expect("Working file is in the catalog"))?;
+        // Entries added since the last update were just recovered
+        store.update(&working)?;
+        working
+      }
+      None => store.create(org, title)?,
+    };
+
+    // Continue after the highest number stored so far: the catalog
+    // knows the sealed files, the working file was just recovered
+    let next_seq = store
+      .files()
+      .filter_map(|info| info.index.last_seq())
+      .chain(working.index.last_seq())
+      .max()
+      .map_or(1, |last| last + 1);
+
+    Ok(State {
+      settings,
+      store,
+      working,
+      next_seq,
+      unlisted: 0,
+    })
+  }
+  fn add_entry(&mut self, formats: &Registry, mut entry: Entry) -> crate::Result<Entry> {
+    // Received is the server side ingest time, the event
+    // time at the source is parsed from the log itself
+    entry.received = Utc::now();
+    // Parse structured fields by log format
+    formats.parse_entry(&mut entry);
+    entry.seq = self.next_seq;
+    self.working.add_entry(entry.clone())?;
+    self.next_seq += 1;
+    // Keep the dates and counts of the catalog close to the file,
+    // readers pick the files of a query by them
+    self.unlisted += 1;
+    if self.unlisted >= CATALOG_EVERY {
+      self.store.update(&self.working)?;
+      self.unlisted = 0;
+    }
+    Ok(entry)
+  }
+  /// Seal working, continue in a new file with the next id
+  /// and move the sealed one into the archive
+  ///
+  /// The archived file keeps its id, so it is still found
+  /// through the store catalog.
+  fn rotate(&mut self) -> crate::Result<()> {
+    self.store.seal(&mut self.working)?;
+    let org = self.working.header.org.clone();
+    let title = self.working.header.title.clone();
+    let new_working = self.store.create(org, title)?;
+    self.unlisted = 0;
+    let sealed = std::mem::replace(&mut self.working, new_working);
+    let sealed_id = sealed.header.id;
+    // Release the writer lock before the file is moved
+    drop(sealed);
+    self.store.archive(sealed_id)
+  }
+}
+
+#[derive(Clone)]
+pub struct Logger {
+  state: Arc<Mutex<State>>,
   broadcast_tx: tokio::sync::broadcast::Sender<Entry>,
+  /// Parsers filling the structured fields of new entries
+  formats: Arc<Registry>,
 }
 
 impl Logger {
-  /// Init Logger
-  pub async fn init(file_max_count: i32) -> crate::Result<Logger> {
+  /// Init Logger, new files get `org` and `title` in their header
+  pub async fn init(org: String, title: String, file_max_count: i32) -> crate::Result<Logger> {
     // Load settings
     let settings = Settings::load(file_max_count).await?;
 
     // Init broadcast
     let (broadcast_tx, _) = tokio::sync::broadcast::channel(16);
 
-    // Init working
-    let working = if Path::new(WORKING_PATH).exists() {
-      LogFile::open(WORKING_PATH).await?
-    } else {
-      LogFile::init(
-        Path::new(WORKING_PATH),
-        config.org.clone(),
-        config.title.clone(),
-        InternalData::read()?.counter,
-      )
-      .await?
-    };
+    // Init store and working
+    let state = spawn_blocking(move || State::open(settings, org, title))
+      .await
+      .expect("Error during spawn blocking when opening the working file")?;
 
     let res = Logger {
-      config: Arc::new(Mutex::new(config)),
-      working,
+      state: Arc::new(Mutex::new(state)),
       broadcast_tx,
+      formats: Arc::new(Registry::default()),
     };
 
-    let mut _logger = res.clone();
+    let _logger = res.clone();
 
     // Spawn background archive checking process
     tokio::task::spawn(async move {
@@ -124,7 +204,10 @@ impl Logger {
         .with_second(59)
         .unwrap();
 
-      let duration = start.signed_duration_since(now).to_std().unwrap();
+      let duration = start
+        .signed_duration_since(now)
+        .to_std()
+        .unwrap_or_default();
 
       let period = chrono::Duration::days(1).to_std().unwrap();
 
@@ -134,7 +217,9 @@ impl Logger {
         // Wait till tick time
         interval.tick().await;
 
-        _logger.archive().await.unwrap();
+        if let Err(e) = _logger.archive().await {
+          log::error!("Cannot archive working file: {e}");
+        }
       }
     });
 
@@ -142,52 +227,31 @@ impl Logger {
   }
   /// Archive current working log
   /// and create a new one
-  pub async fn archive(&mut self) -> crate::Result<()> {
-    // Get working header
-    let working = self.working.clone();
-
-    // Get working header
-    let working_header = working.header().await;
-
-    // Spawn fs tasks on blocking thread
-    tokio::task::spawn_blocking(move || -> crate::Result<()> {
-      // Create date string
-      let date = {
-        let now = Utc::now();
-        let (_, year) = now.year_ce();
-        let date = format!("{}_{}_{}", year, now.month(), now.day());
-        date
-      };
-      let working_path = Path::new(WORKING_PATH);
-
-      let new_path = Path::new(ARCHIVE_PATH).join(format!(
-        "{}_{}_{}_{}.twl",
-        working_header.org, working_header.title, date, working_header.id
-      ));
-      // Only archive when working file exist
-      if working_path.exists() {
-        // Move working file to the archive folder
-        std::fs::rename(working_path, new_path).map_err(|e| e.to_string())?;
-        // Increment counter
-        InternalData::increment_counter()?;
-      }
-      Ok(())
-    })
-    .await
-    .expect("Error during spawn blocking when archiving")
+  pub async fn archive(&self) -> crate::Result<()> {
+    let mut state = self.state.clone().lock_owned().await;
+
+    // Sealing syncs the file and builds its full text index,
+    // so it runs on a blocking thread like the renames
+    let mut state = spawn_blocking(move || state.rotate().map(|_| state))
+      .await
+      .expect("Error during spawn blocking when archiving")?;
+
+    state.settings.working_id = state.working.header.id as i32;
+    state.settings.save().await
   }
   /// Add log entry
-  pub async fn add_entry(&mut self, entry: crate::fs::Entry) -> crate::Result<()> {
-    // Cloning working
-    self.working.add_entry(entry.clone()).await?;
-    self
-      .broadcast_tx
-      .send(entry)
-      .expect("Error sending log entry to broadcast subscribers");
+  pub async fn add_entry(&self, entry: Entry) -> crate::Result<()> {
+    let mut state = self.state.clone().lock_owned().await;
+    let formats = self.formats.clone();
+    let entry = spawn_blocking(move || state.add_entry(&formats, entry))
+      .await
+      .expect("Error during spawn blocking when adding an entry")?;
+    // Fails only when nobody is watching
+    let _ = self.broadcast_tx.send(entry);
     Ok(())
   }
   /// Subscribe for events
-  pub async fn watch(&mut self) -> Receiver<Entry> {
+  pub fn watch(&self) -> Receiver<Entry> {
     self.broadcast_tx.subscribe()
   }
 }
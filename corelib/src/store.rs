@@ -0,0 +1,397 @@
+//! Catalog of the log files of a data directory
+//!
+//! A `LogStore` owns a directory of towl files, allocates their ids
+//! and keeps a catalog of them in `{dir}/catalog`: path, header, index
+//! and state of every file. The catalog only caches what the files
+//! themselves hold, so it is rebuilt by scanning the directory when
+//! it is missing or cannot be read.
+use crate::fs::{self, Clock, Entry, Filter, Header, Index, KeyRing, LogFile, Options, TimeRange};
+use crate::Error;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc::Sender;
+
+mod merge;
+
+pub use merge::Merge;
+
+/// Catalog file name in the store directory
+const CATALOG: &str = "catalog";
+/// Directory of archived files, inside the store directory
+const ARCHIVE_DIR: &str = "archive";
+/// Sidecars moved along with an archived file
+const SIDECARS: [&str; 2] = [".sparse", ".idx"];
+
+/// Lifecycle state of a file in the store
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileState {
+  /// Entries are appended, its index in the catalog may lag behind
+  Working,
+  /// Closed, no entries are added any more
+  Sealed,
+  /// Sealed and moved into the `archive` directory
+  Archived,
+}
+
+/// Catalog entry of a file
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FileInfo {
+  pub id: usize,
+  /// Path relative to the store directory
+  pub path: PathBuf,
+  pub header: Header,
+  /// Index of the file when the catalog was last updated
+  pub index: Index,
+  pub state: FileState,
+}
+
+impl FileInfo {
+  /// True if the file may hold entries in `range`
+  pub fn covers(&self, range: &TimeRange) -> bool {
+    if self.state == FileState::Working {
+      return range.overlaps(self.earliest(range.clock), DateTime::<Utc>::MAX_UTC);
+    }
+    match (
+      self.index.first_date(range.clock),
+      self.index.last_date(range.clock),
+    ) {
+      (Some(first), Some(last)) => range.overlaps(first, last),
+      // Sealed without entries
+      _ => false,
+    }
+  }
+  /// Earliest time of the entries the file may hold by `clock`,
+  /// `DateTime::MAX_UTC` if it holds none
+  pub fn earliest(&self, clock: Clock) -> DateTime<Utc> {
+    match (self.state, clock) {
+      // New entries may have arrived since the catalog was updated,
+      // received now, but with any source time
+      (FileState::Working, Clock::Received) => {
+        self.index.first_date(clock).unwrap_or(self.index.opened())
+      }
+      (FileState::Working, Clock::Source) => DateTime::<Utc>::MIN_UTC,
+      _ => self
+        .index
+        .first_date(clock)
+        .unwrap_or(DateTime::<Utc>::MAX_UTC),
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Catalog {
+  /// Id of the next created file
+  next_id: usize,
+  files: BTreeMap<usize, FileInfo>,
+}
+
+impl Catalog {
+  fn new() -> Self {
+    Catalog {
+      next_id: 1,
+      files: BTreeMap::new(),
+    }
+  }
+}
+
+/// Directory of log files with their catalog
+///
+/// Only one writable store may be open per directory, see `open`.
+pub struct LogStore {
+  dir: PathBuf,
+  catalog: Catalog,
+  readonly: bool,
+  /// Exclusive lock of the `catalog.lock` sidecar, held by writable stores
+  _lock: Option<File>,
+}
+
+impl LogStore {
+  /// Open the store in `dir`, creating the directory if needed
+  ///
+  /// Fails with `Locked` if another writable store is open on it.
+  pub fn open<T>(dir: T) -> crate::Result<Self>
+  where
+    T: AsRef<Path>,
+  {
+    let dir = dir.as_ref().to_path_buf();
+    std::fs::create_dir_all(&dir)?;
+    let lock = fs::lock_writer(&dir.join(CATALOG))?;
+    let mut res = LogStore {
+      dir,
+      catalog: Catalog::new(),
+      readonly: false,
+      _lock: Some(lock),
+    };
+    match res.load() {
+      Ok(catalog) => res.catalog = catalog,
+      Err(_) => res.rescan()?,
+    }
+    Ok(res)
+  }
+  /// Open the store in `dir` for reading only
+  ///
+  /// Nothing is written, a missing catalog is rebuilt in memory.
+  pub fn open_readonly<T>(dir: T) -> crate::Result<Self>
+  where
+    T: AsRef<Path>,
+  {
+    let mut res = LogStore {
+      dir: dir.as_ref().to_path_buf(),
+      catalog: Catalog::new(),
+      readonly: true,
+      _lock: None,
+    };
+    match res.load() {
+      Ok(catalog) => res.catalog = catalog,
+      Err(_) => res.rescan()?,
+    }
+    Ok(res)
+  }
+  /// Store directory
+  pub fn dir(&self) -> &Path {
+    &self.dir
+  }
+  /// Catalog entries by id
+  pub fn files(&self) -> impl Iterator<Item = &FileInfo> {
+    self.catalog.files.values()
+  }
+  pub fn get(&self, id: usize) -> Option<&FileInfo> {
+    self.catalog.files.get(&id)
+  }
+  /// Full path of a file
+  pub fn path(&self, id: usize) -> Option<PathBuf> {
+    self.get(id).map(|info| self.dir.join(&info.path))
+  }
+  /// Newest working file
+  pub fn working(&self) -> Option<&FileInfo> {
+    self
+      .catalog
+      .files
+      .values()
+      .rev()
+      .find(|info| info.state == FileState::Working)
+  }
+  /// Files that may hold entries in `range`, oldest first
+  ///
+  /// Decided by the first and last date of their index, so a file
+  /// is only opened when it can hold matching entries.
+  pub fn covering(&self, range: &TimeRange) -> Vec<&FileInfo> {
+    let mut res: Vec<&FileInfo> = self.files().filter(|info| info.covers(range)).collect();
+    // Files without entries sort last, by id
+    res.sort_by_key(|info| (info.earliest(range.clock), info.id));
+    res
+  }
+  /// Entries of every file matching `filter`, ordered by the time
+  /// of its clock
+  ///
+  /// Only the files covering the time range of the filter are read,
+  /// each opened once the merge reaches its earliest entry, see
+  /// `Merge`. Entries are read on the calling thread, so call it
+  /// from a blocking one.
+  pub fn query(&self, filter: Filter) -> Merge {
+    self.query_with(filter, &KeyRing::default())
+  }
+  /// Like `query`, with the keys of encrypted files
+  pub fn query_with(&self, filter: Filter, keys: &KeyRing) -> Merge {
+    let files = self
+      .covering(&filter.range)
+      .into_iter()
+      .map(|info| (info.earliest(filter.range.clock), self.dir.join(&info.path)))
+      .collect();
+    Merge::new(files, filter, keys)
+  }
+  /// Stream entries of every file matching `filter` into `tx`,
+  /// ordered by time
+  ///
+  /// Blocks until done, call it from a blocking thread. Stops without
+  /// error when the receiver is dropped.
+  pub fn stream_query(
+    &self,
+    filter: Filter,
+    keys: &KeyRing,
+    tx: Sender<Entry>,
+  ) -> crate::Result<()> {
+    for entry in self.query_with(filter, keys) {
+      if tx.blocking_send(entry?).is_err() {
+        // Receiver is gone, nobody needs the rest
+        break;
+      }
+    }
+    Ok(())
+  }
+  /// Create a new working file with the next id
+  pub fn create(&mut self, org: String, title: String) -> crate::Result<LogFile> {
+    self.create_with(org, title, Options::default())
+  }
+  /// Create a new working file with the given options
+  pub fn create_with(
+    &mut self,
+    org: String,
+    title: String,
+    options: Options,
+  ) -> crate::Result<LogFile> {
+    self.check_writable()?;
+    let id = self.catalog.next_id;
+    // The id is used up even if the file cannot be created
+    self.catalog.next_id += 1;
+    self.save()?;
+    let log = LogFile::init_with(&self.dir.to_string_lossy(), org, title, id, options)?;
+    self.catalog.files.insert(
+      id,
+      FileInfo {
+        id,
+        path: PathBuf::from(format!("{id}.towl")),
+        header: log.header.clone(),
+        index: log.index.clone(),
+        state: FileState::Working,
+      },
+    );
+    self.save()?;
+    Ok(log)
+  }
+  /// Update the catalog entry of `log` from its current index
+  ///
+  /// Call it now and then on the working file, so the catalog
+  /// does not lag behind too much; the `Logger` does so every
+  /// `logger::CATALOG_EVERY` entries.
+  pub fn update(&mut self, log: &LogFile) -> crate::Result<()> {
+    self.check_writable()?;
+    let id = log.header.id;
+    let info = self
+      .catalog
+      .files
+      .get_mut(&id)
+      .ok_or(Error::UnknownId(id))?;
+    info.index = log.index.clone();
+    if info.state == FileState::Working && log.index.closed().is_some() {
+      info.state = FileState::Sealed;
+    }
+    self.save()
+  }
+  /// Close a working file and mark it sealed
+  pub fn seal(&mut self, log: &mut LogFile) -> crate::Result<()> {
+    self.check_writable()?;
+    log.close()?;
+    self.update(log)
+  }
+  /// Move a sealed file and its sidecars into the `archive` directory
+  ///
+  /// The file keeps its id and name, so it is still found by id.
+  /// Fails with `Locked` while a writer has it open.
+  pub fn archive(&mut self, id: usize) -> crate::Result<()> {
+    self.check_writable()?;
+    let info = self.get(id).ok_or(Error::UnknownId(id))?;
+    match info.state {
+      FileState::Working => return Err(Error::NotSealed(id)),
+      FileState::Archived => return Ok(()),
+      FileState::Sealed => (),
+    }
+    let from = self.dir.join(&info.path);
+    let path = Path::new(ARCHIVE_DIR).join(from.file_name().unwrap_or_default());
+    let to = self.dir.join(&path);
+    std::fs::create_dir_all(self.dir.join(ARCHIVE_DIR))?;
+    {
+      // No writer may append while the file is moved
+      let _lock = fs::lock_writer(&from)?;
+      for ext in SIDECARS {
+        let sidecar = fs::sidecar_path(&from, ext);
+        if sidecar.exists() {
+          std::fs::rename(sidecar, fs::sidecar_path(&to, ext))?;
+        }
+      }
+      std::fs::rename(&from, &to)?;
+    }
+    let _ = std::fs::remove_file(fs::sidecar_path(&from, ".lock"));
+    if let Some(info) = self.catalog.files.get_mut(&id) {
+      info.path = path;
+      info.state = FileState::Archived;
+    }
+    self.save()
+  }
+  /// Rebuild the catalog from the files of the directory
+  ///
+  /// Every towl file is taken, whatever its name, e.g. files
+  /// archived by older releases as `{org}_{title}_{date}_{id}.twl`.
+  /// Files that cannot be read are left out with a warning.
+  pub fn rescan(&mut self) -> crate::Result<()> {
+    let mut catalog = Catalog::new();
+    for (dir, archived) in [(PathBuf::new(), false), (PathBuf::from(ARCHIVE_DIR), true)] {
+      let entries = match std::fs::read_dir(self.dir.join(&dir)) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+        Err(e) => return Err(e.into()),
+      };
+      for entry in entries {
+        let entry = entry?;
+        let full_path = entry.path();
+        if !entry.file_type()?.is_file() || !LogFile::is_towl_file(&full_path.to_string_lossy()) {
+          continue;
+        }
+        let (header, index) = match fs::read_meta(&full_path) {
+          Ok(meta) => meta,
+          Err(e) => {
+            log::warn!("Skipping {} in store catalog: {e}", full_path.display());
+            continue;
+          }
+        };
+        let id = header.id;
+        if let Some(other) = catalog.files.get(&id) {
+          log::warn!(
+            "Skipping {}, id {id} is already taken by {}",
+            full_path.display(),
+            other.path.display()
+          );
+          continue;
+        }
+        let state = match (archived, index.closed()) {
+          (true, _) => FileState::Archived,
+          (false, Some(_)) => FileState::Sealed,
+          (false, None) => FileState::Working,
+        };
+        catalog.next_id = catalog.next_id.max(id + 1);
+        catalog.files.insert(
+          id,
+          FileInfo {
+            id,
+            path: dir.join(entry.file_name()),
+            header,
+            index,
+            state,
+          },
+        );
+      }
+    }
+    // Never hand out an id again, even if its file is gone
+    catalog.next_id = catalog.next_id.max(self.catalog.next_id);
+    self.catalog = catalog;
+    if !self.readonly {
+      self.save()?;
+    }
+    Ok(())
+  }
+  fn check_writable(&self) -> crate::Result<()> {
+    if self.readonly {
+      return Err(Error::ReadOnly);
+    }
+    Ok(())
+  }
+  fn load(&self) -> crate::Result<Catalog> {
+    let bytes = std::fs::read(self.dir.join(CATALOG))?;
+    Ok(bincode::deserialize(&bytes)?)
+  }
+  /// Save catalog through a temp file renamed over the old one
+  fn save(&self) -> crate::Result<()> {
+    let bytes = bincode::serialize(&self.catalog).map_err(Error::Encode)?;
+    let path = self.dir.join(CATALOG);
+    let tmp = fs::sidecar_path(&path, ".tmp");
+    let mut file = File::create(&tmp)?;
+    file.write_all(&bytes)?;
+    file.sync_data()?;
+    std::fs::rename(tmp, path)?;
+    Ok(())
+  }
+}
@@ -0,0 +1,214 @@
+//! Time ordered reads over many files
+//!
+//! Entries are merged by a k-way merge on the time of the filter's
+//! clock. Files are opened in the order of their earliest entry, each
+//! only once the merge reaches that time, so a long query keeps few
+//! files open at once.
+//! A file written in received order is read straight through. By
+//! source time, or if the server clock went backwards while writing
+//! it, its segments are read in the order of their earliest time and
+//! merged the same way, see the sparse index: only the segments whose
+//! times overlap are held in memory, all of them in the worst case.
+//! Entries are read on the calling thread.
+use crate::fs::{Clock, Entry, Filter, KeyRing, LogFile, Segment};
+use chrono::{DateTime, Utc};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, VecDeque};
+use std::path::{Path, PathBuf};
+
+/// Entries of a file in merge order
+type Entries = Box<dyn Iterator<Item = crate::Result<Entry>> + Send>;
+
+/// Time ordered entries of many files, see `LogStore::query`
+///
+/// Yields an error for every file that cannot be read, the
+/// other files are still merged.
+pub struct Merge {
+  filter: Filter,
+  keys: KeyRing,
+  /// Files not opened yet with their earliest time, the earliest last
+  files: Vec<(DateTime<Utc>, PathBuf)>,
+  sources: Vec<Entries>,
+  /// Next entry of every source with one
+  heads: BinaryHeap<Reverse<Head>>,
+  /// Sources whose next entry is not read yet
+  pending: Vec<usize>,
+}
+
+impl Merge {
+  /// Merge the entries of `files`, each given with the earliest time
+  /// of its entries by the filter's clock, in the order of that time
+  pub(crate) fn new(
+    mut files: Vec<(DateTime<Utc>, PathBuf)>,
+    filter: Filter,
+    keys: &KeyRing,
+  ) -> Self {
+    files.reverse();
+    Self {
+      filter,
+      keys: keys.clone(),
+      files,
+      sources: Vec::new(),
+      heads: BinaryHeap::new(),
+      pending: Vec::new(),
+    }
+  }
+  /// True if the next file may hold an entry before every head
+  fn open_due(&self) -> bool {
+    match (self.files.last(), self.heads.peek()) {
+      (Some((first, _)), Some(Reverse(head))) => *first <= head.time,
+      (Some(_), None) => true,
+      (None, _) => false,
+    }
+  }
+}
+
+impl Iterator for Merge {
+  type Item = crate::Result<Entry>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    loop {
+      // The smallest head is only known once every source has one
+      while let Some(source) = self.pending.pop() {
+        match self.sources[source].next() {
+          Some(Ok(entry)) => self.heads.push(Reverse(Head {
+            time: entry.time(self.filter.range.clock),
+            order: source,
+            entry,
+          })),
+          Some(Err(e)) => {
+            self.pending.push(source);
+            return Some(Err(e));
+          }
+          // File done, release it
+          None => self.sources[source] = Box::new(std::iter::empty()),
+        }
+      }
+      if !self.open_due() {
+        break;
+      }
+      let (_, path) = self.files.pop()?;
+      match open(&path, &self.filter, &self.keys) {
+        Ok(entries) => {
+          self.pending.push(self.sources.len());
+          self.sources.push(entries);
+        }
+        Err(e) => return Some(Err(e)),
+      }
+    }
+    let Reverse(head) = self.heads.pop()?;
+    self.pending.push(head.order);
+    Some(Ok(head.entry))
+  }
+}
+
+/// Next entry of a source, ordered by time, then by the order
+/// its source was opened in
+struct Head {
+  time: DateTime<Utc>,
+  order: usize,
+  entry: Entry,
+}
+
+impl Ord for Head {
+  fn cmp(&self, other: &Self) -> Ordering {
+    (self.time, self.order).cmp(&(other.time, other.order))
+  }
+}
+
+impl PartialOrd for Head {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl PartialEq for Head {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other) == Ordering::Equal
+  }
+}
+
+impl Eq for Head {}
+
+/// Entries of a file matching `filter` in time order
+fn open(path: &Path, filter: &Filter, keys: &KeyRing) -> crate::Result<Entries> {
+  let log = LogFile::open_readonly_with(path, keys)?;
+  // Files are written in received order, unless the server clock
+  // went backwards
+  if filter.range.clock == Clock::Received && log.monotonic() {
+    return Ok(Box::new(log.iter_filter(filter.clone())?));
+  }
+  // Source times of different hosts are not ordered either
+  Ok(Box::new(Segments {
+    segments: log.segments_by_time(filter)?.into(),
+    log,
+    filter: filter.clone(),
+    entries: BinaryHeap::new(),
+    errors: VecDeque::new(),
+    read: 0,
+  }))
+}
+
+/// Entries of a file not stored in time order, merged segment by
+/// segment
+struct Segments {
+  log: LogFile,
+  filter: Filter,
+  /// Segments not read yet with their earliest time, the earliest first
+  segments: VecDeque<Segment>,
+  /// Matching entries of the segments read
+  entries: BinaryHeap<Reverse<Head>>,
+  /// Damaged records of the segments read, yielded first
+  errors: VecDeque<crate::Error>,
+  /// Entries read so far, keeps ties in read order
+  read: usize,
+}
+
+impl Segments {
+  /// Read every matching entry of a segment
+  fn read_segment(&mut self, ranges: Vec<(u64, u64)>) {
+    let clock = self.filter.range.clock;
+    let entries = match self.log.iter_ranges(ranges, self.filter.clone()) {
+      Ok(entries) => entries,
+      Err(e) => return self.errors.push_back(e),
+    };
+    for entry in entries {
+      match entry {
+        Ok(entry) => {
+          self.entries.push(Reverse(Head {
+            time: entry.time(clock),
+            order: self.read,
+            entry,
+          }));
+          self.read += 1;
+        }
+        Err(e) => self.errors.push_back(e),
+      }
+    }
+  }
+}
+
+impl Iterator for Segments {
+  type Item = crate::Result<Entry>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    // Read segments until none can hold an entry before the
+    // earliest one read
+    while let Some((first, _)) = self.segments.front() {
+      if self
+        .entries
+        .peek()
+        .is_some_and(|Reverse(head)| head.time < *first)
+      {
+        break;
+      }
+      let (_, ranges) = self.segments.pop_front()?;
+      self.read_segment(ranges);
+    }
+    if let Some(e) = self.errors.pop_front() {
+      return Some(Err(e));
+    }
+    let Reverse(head) = self.entries.pop()?;
+    Some(Ok(head.entry))
+  }
+}
@@ -0,0 +1,159 @@
+mod common;
+
+use common::{entry, init};
+use corelib::fs::{migrate_to, verify, Compression, Entry, LogFile, Options};
+use corelib::Error;
+use std::path::{Path, PathBuf};
+
+/// Create a closed, chained log file with `count` entries
+fn create(dir: &Path, compression: Compression, count: usize) -> PathBuf {
+  let options = Options {
+    compression,
+    hash_chain: true,
+    ..Options::default()
+  };
+  let mut log = init(dir, options);
+  for i in 0..count / 2 {
+    log.add_entry(entry(i)).unwrap();
+  }
+  drop(log);
+
+  // Reopening continues the chain
+  let path = common::path(dir);
+  let mut log = LogFile::open(&path).unwrap();
+  let batch: Vec<Entry> = (count / 2..count).map(entry).collect();
+  log.add_entries(&batch).unwrap();
+  log.close().unwrap();
+  path
+}
+
+/// Byte ranges of the data records
+fn records(path: &Path) -> Vec<(usize, usize)> {
+  let log = LogFile::open_readonly(path).unwrap();
+  let start = log.seek_to_count(0).unwrap() as usize;
+  let data = std::fs::read(path).unwrap();
+  let mut res = Vec::new();
+  let mut offset = start;
+  while offset + 8 <= data.len() {
+    let len = u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
+    res.push((offset, offset + 8 + len));
+    offset += 8 + len;
+  }
+  res
+}
+
+fn broken_at(path: &Path) -> Option<usize> {
+  verify(path).unwrap().broken.map(|broken| broken.entry)
+}
+
+#[test]
+fn intact_chain_verifies() {
+  for compression in [Compression::None, Compression::Lz4] {
+    let dir = tempfile::tempdir().unwrap();
+    let path = create(dir.path(), compression, 600);
+
+    let verification = verify(&path).unwrap();
+    assert!(verification.is_intact());
+    assert_eq!(verification.entries, 600);
+    let log = LogFile::open_readonly(&path).unwrap();
+    assert_eq!(log.index.digest(), Some(&verification.digest));
+  }
+}
+
+#[test]
+fn edited_record_breaks_chain() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = create(dir.path(), Compression::None, 20);
+  let (start, end) = records(&path)[5];
+
+  // Edit the last payload byte and fix the checksum
+  let mut data = std::fs::read(&path).unwrap();
+  data[end - 1] ^= 1;
+  let crc = crc32fast::hash(&data[start + 8..end]);
+  data[start + 4..start + 8].copy_from_slice(&crc.to_le_bytes());
+  std::fs::write(&path, data).unwrap();
+
+  let broken = verify(&path).unwrap().broken.unwrap();
+  assert_eq!((broken.entry, broken.offset), (5, start as u64));
+}
+
+#[test]
+fn removed_record_breaks_chain() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = create(dir.path(), Compression::None, 20);
+  let (start, end) = records(&path)[7];
+
+  let mut data = std::fs::read(&path).unwrap();
+  data.drain(start..end);
+  std::fs::write(&path, data).unwrap();
+
+  assert_eq!(broken_at(&path), Some(7));
+}
+
+#[test]
+fn reordered_records_break_chain() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = create(dir.path(), Compression::None, 20);
+  let records = records(&path);
+  let (a, b) = (records[3], records[4]);
+
+  // Entries 3 and 4 have records of the same size
+  let mut data = std::fs::read(&path).unwrap();
+  let first = data[a.0..a.1].to_vec();
+  let second = data[b.0..b.1].to_vec();
+  data.splice(a.0..b.1, second.into_iter().chain(first));
+  std::fs::write(&path, data).unwrap();
+
+  assert_eq!(broken_at(&path), Some(3));
+}
+
+#[test]
+fn truncated_file_breaks_chain() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = create(dir.path(), Compression::None, 20);
+  let (start, _) = *records(&path).last().unwrap();
+
+  // Only the digest in the index knows about the last record
+  let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+  file.set_len(start as u64).unwrap();
+  drop(file);
+
+  assert_eq!(broken_at(&path), Some(19));
+}
+
+#[test]
+fn migrated_file_keeps_verifying() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = create(dir.path(), Compression::None, 20);
+  let target = dir.path().join("2.towl");
+
+  migrate_to(&path, &target).unwrap();
+  assert_eq!(broken_at(&target), None);
+}
+
+#[test]
+fn broken_chain_is_not_migrated() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = create(dir.path(), Compression::None, 20);
+  let (start, end) = records(&path)[7];
+  let mut data = std::fs::read(&path).unwrap();
+  data.drain(start..end);
+  std::fs::write(&path, data).unwrap();
+
+  let target = dir.path().join("2.towl");
+  assert!(matches!(
+    migrate_to(&path, &target),
+    Err(Error::Corrupt { offset }) if offset == start as u64
+  ));
+}
+
+#[test]
+fn unchained_file_cannot_be_verified() {
+  let dir = tempfile::tempdir().unwrap();
+  init(dir.path(), Options::default());
+
+  assert!(matches!(
+    verify(common::path(dir.path())),
+    Err(Error::NoHashChain)
+  ));
+}
@@ -0,0 +1,49 @@
+//! Fixtures shared by the integration tests
+#![allow(dead_code)]
+
+use chrono::Utc;
+use corelib::fs::{Entry, LogFile, Options};
+use std::path::{Path, PathBuf};
+
+/// Entry `i` with the text "log entry {i}"
+pub fn entry(i: usize) -> Entry {
+  text_entry(format!("log entry {i}"))
+}
+
+/// Text entry of sender "test", received now
+pub fn text_entry(log_entry: String) -> Entry {
+  Entry {
+    sender: "test".to_string(),
+    received: Utc::now(),
+    log_format: 0,
+    log_entry,
+    fields: None,
+    seq: 0,
+  }
+}
+
+/// Create log file 1 in `dir`
+pub fn init(dir: &Path, options: Options) -> LogFile {
+  LogFile::init_with(
+    dir.to_str().unwrap(),
+    "org".to_string(),
+    "title".to_string(),
+    1,
+    options,
+  )
+  .unwrap()
+}
+
+/// Path of log file 1 in `dir`
+pub fn path(dir: &Path) -> PathBuf {
+  dir.join("1.towl")
+}
+
+/// Text of every entry, in file order
+pub fn messages(log: &LogFile) -> Vec<String> {
+  log
+    .iter()
+    .unwrap()
+    .map(|entry| entry.unwrap().log_entry)
+    .collect()
+}
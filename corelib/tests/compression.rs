@@ -0,0 +1,113 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use common::{messages, text_entry};
+use corelib::fs::{Compression, Entry, LogFile, Options};
+use std::path::Path;
+
+fn entry(i: usize) -> Entry {
+  Entry {
+    received: Utc::now() + Duration::seconds(i as i64),
+    log_format: 1,
+    ..text_entry(format!(
+      "{{\"MESSAGE\":\"same json line again {i}\",\"_SYSTEMD_UNIT\":\"nginx.service\"}}"
+    ))
+  }
+}
+
+fn init(dir: &Path, compression: Compression) -> LogFile {
+  common::init(
+    dir,
+    Options {
+      compression,
+      ..Options::default()
+    },
+  )
+}
+
+/// Entries 0..n in batches of the given sizes
+fn add_batches(log: &mut LogFile, sizes: &[usize]) -> usize {
+  let mut n = 0;
+  for size in sizes {
+    let batch: Vec<Entry> = (n..n + size).map(entry).collect();
+    log.add_entries(&batch).unwrap();
+    n += size;
+  }
+  n
+}
+
+fn round_trip(compression: Compression) {
+  let dir = tempfile::tempdir().unwrap();
+  let path = common::path(dir.path());
+  let mut log = init(dir.path(), compression);
+  let n = add_batches(&mut log, &[1, 7, 500, 3000, 1, 1, 2000]);
+  log.sync().unwrap();
+
+  let check = |log: &LogFile| {
+    let expected: Vec<_> = (0..n).map(|i| entry(i).log_entry).collect();
+    assert_eq!(messages(log), expected);
+    for i in [0, 1, 8, 300, 1024, 2049, 4000, n - 1] {
+      let first = log.iter_from(i).unwrap().next().unwrap().unwrap();
+      assert_eq!(first.log_entry, expected[i]);
+    }
+    let tail: Vec<_> = log.tail(3).unwrap().map(|e| e.unwrap().log_entry).collect();
+    assert_eq!(tail, expected[n - 3..]);
+    let rev: Vec<_> = log
+      .iter_rev()
+      .unwrap()
+      .map(|e| e.unwrap().log_entry)
+      .collect();
+    assert_eq!(rev.first(), expected.last());
+    assert_eq!(rev.len(), n);
+  };
+  check(&log);
+  log.close().unwrap();
+  drop(log);
+
+  let log = LogFile::open(&path).unwrap();
+  assert!(log.recovery().is_clean());
+  assert_eq!(log.header.compression(), compression);
+  assert_eq!(log.index.count(), n);
+  check(&log);
+}
+
+#[test]
+fn zstd_round_trip() {
+  round_trip(Compression::Zstd);
+}
+
+#[test]
+fn lz4_round_trip() {
+  round_trip(Compression::Lz4);
+}
+
+#[test]
+fn open_block_is_written_on_sync_and_drop() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = common::path(dir.path());
+  let mut log = init(dir.path(), Compression::Zstd);
+  add_batches(&mut log, &[1, 1, 1]);
+
+  // Not full yet, so nothing is written
+  assert_eq!(log.index.count(), 0);
+  log.sync().unwrap();
+  assert_eq!(messages(&log).len(), 3);
+
+  log.add_entry(entry(3)).unwrap();
+  drop(log);
+  let log = LogFile::open(&path).unwrap();
+  assert_eq!(log.index.count(), 4);
+}
+
+#[test]
+fn single_appends_share_blocks() {
+  let size = |compression| {
+    let dir = tempfile::tempdir().unwrap();
+    let mut log = init(dir.path(), compression);
+    add_batches(&mut log, &[1; 1000]);
+    log.close().unwrap();
+    log.index.stats().bytes
+  };
+
+  assert!(size(Compression::Zstd) * 4 < size(Compression::None));
+}
@@ -0,0 +1,185 @@
+mod common;
+
+use common::{init, text_entry};
+use corelib::fs::{
+  Cipher, Compression, Encryption, Entry, Key, KeyFilter, KeyRing, LogFile, Options, TimeRange,
+};
+use corelib::Error;
+use std::path::{Path, PathBuf};
+
+fn entry(i: usize) -> Entry {
+  text_entry(format!("customer-4711 entry {i}"))
+}
+
+fn key() -> Key {
+  Key::new("k1".to_string(), [7; 32])
+}
+
+/// Create an encrypted log file with `count` entries and return its path
+fn create(dir: &Path, cipher: Cipher, compression: Compression, count: usize) -> PathBuf {
+  let options = Options {
+    compression,
+    encryption: Some(Encryption { cipher, key: key() }),
+    hash_chain: false,
+  };
+  let mut log = init(dir, options);
+  let batch: Vec<Entry> = (0..count).map(entry).collect();
+  log.add_entries(&batch).unwrap();
+  log.close().unwrap();
+  common::path(dir)
+}
+
+fn round_trip(cipher: Cipher, compression: Compression) {
+  let dir = tempfile::tempdir().unwrap();
+  let path = create(dir.path(), cipher, compression, 3000);
+
+  // Entries are not stored in plain text
+  let raw = std::fs::read(&path).unwrap();
+  assert!(!raw.windows(13).any(|w| w == b"customer-4711"));
+
+  let log = LogFile::open_with(&path, &KeyRing::from(key())).unwrap();
+  assert!(log.recovery().is_clean());
+  assert_eq!(log.header.key_id(), Some("k1"));
+  let entries: Vec<_> = log.iter().unwrap().map(|e| e.unwrap().log_entry).collect();
+  assert_eq!(entries.len(), 3000);
+  assert_eq!(entries[2999], "customer-4711 entry 2999");
+  let last = log.iter_rev().unwrap().next().unwrap().unwrap();
+  assert_eq!(last.log_entry, "customer-4711 entry 2999");
+}
+
+#[test]
+fn aes_round_trip() {
+  round_trip(Cipher::Aes256Gcm, Compression::None);
+}
+
+#[test]
+fn chacha_zstd_round_trip() {
+  round_trip(Cipher::ChaCha20Poly1305, Compression::Zstd);
+}
+
+#[test]
+fn missing_key_is_rejected() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = create(dir.path(), Cipher::Aes256Gcm, Compression::None, 3);
+
+  let other = KeyRing::from(Key::new("k2".to_string(), [7; 32]));
+  assert!(matches!(LogFile::open(&path), Err(Error::MissingKey(id)) if id == "k1"));
+  assert!(matches!(
+    LogFile::open_readonly_with(&path, &other),
+    Err(Error::MissingKey(_))
+  ));
+}
+
+#[test]
+fn wrong_key_is_rejected() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = create(dir.path(), Cipher::Aes256Gcm, Compression::None, 3);
+
+  let wrong = KeyRing::from(Key::new("k1".to_string(), [8; 32]));
+  assert!(matches!(
+    LogFile::open_with(&path, &wrong),
+    Err(Error::WrongKey(_))
+  ));
+  assert!(matches!(
+    LogFile::open_readonly_with(&path, &wrong),
+    Err(Error::WrongKey(_))
+  ));
+}
+
+#[test]
+fn tampered_record_fails_to_decrypt() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = create(dir.path(), Cipher::ChaCha20Poly1305, Compression::None, 3);
+  let keys = KeyRing::from(key());
+  let offset = LogFile::open_readonly_with(&path, &keys)
+    .unwrap()
+    .seek_to_count(1)
+    .unwrap() as usize;
+
+  // Flip a sealed byte and fix the checksum, so only the cipher notices
+  let mut data = std::fs::read(&path).unwrap();
+  let len = u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
+  let payload = offset + 8..offset + 8 + len;
+  data[payload.end - 1] ^= 1;
+  let crc = crc32fast::hash(&data[payload]);
+  data[offset + 4..offset + 8].copy_from_slice(&crc.to_le_bytes());
+  std::fs::write(&path, data).unwrap();
+
+  let log = LogFile::open_readonly_with(&path, &keys).unwrap();
+  let entries: Vec<_> = log.iter().unwrap().collect();
+  assert_eq!(entries.len(), 3);
+  assert!(matches!(entries[1], Err(Error::Decrypt)));
+  assert!(entries[0].is_ok() && entries[2].is_ok());
+}
+
+#[test]
+fn key_file_is_loaded() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = create(dir.path(), Cipher::Aes256Gcm, Compression::Lz4, 10);
+  let key_file = dir.path().join("keys");
+  std::fs::write(
+    &key_file,
+    format!(
+      "# keys\nother {}\n\nk1 {}\n",
+      "ab".repeat(32),
+      "07".repeat(32)
+    ),
+  )
+  .unwrap();
+
+  let keys = KeyRing::load(&key_file).unwrap();
+  let log = LogFile::open_readonly_with(&path, &keys).unwrap();
+  assert_eq!(log.iter().unwrap().count(), 10);
+
+  std::fs::write(&key_file, "bad line\n").unwrap();
+  assert!(matches!(
+    KeyRing::load(&key_file),
+    Err(Error::KeyFile { line: 1 })
+  ));
+}
+
+#[test]
+fn metadata_keeps_no_sender_names() {
+  let dir = tempfile::tempdir().unwrap();
+  let options = Options {
+    compression: Compression::None,
+    encryption: Some(Encryption {
+      cipher: Cipher::Aes256Gcm,
+      key: key(),
+    }),
+    hash_chain: false,
+  };
+  let mut log = init(dir.path(), options);
+  let batch: Vec<Entry> = (0..3000)
+    .map(|i| Entry {
+      sender: format!("web-{}", i % 3),
+      ..entry(i)
+    })
+    .collect();
+  log.add_entries(&batch).unwrap();
+  log.close().unwrap();
+  drop(log);
+
+  let path = common::path(dir.path());
+  let log = LogFile::open_readonly_with(&path, &KeyRing::from(key())).unwrap();
+  let stats = log.index.stats();
+  assert!(stats.senders.is_empty());
+  assert_eq!(stats.other_senders, 3000);
+  for file in [path.clone(), dir.path().join("1.towl.sparse")] {
+    let raw = std::fs::read(file).unwrap();
+    assert!(!raw.windows(5).any(|w| w == b"web-1"));
+  }
+
+  // Segments are not skipped without bloom filters
+  let keys = KeyFilter {
+    senders: vec!["web-1".to_string()],
+    ..KeyFilter::default()
+  };
+  let found = log.iter_keys(TimeRange::default(), keys).unwrap();
+  assert_eq!(
+    found
+      .filter(|e| e.as_ref().unwrap().sender == "web-1")
+      .count(),
+    1000
+  );
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use common::{entry, init};
+use corelib::fs::{Compression, Durability, LogFile, Options};
+use std::time::Duration;
+
+/// Compressed file, so a sync shows as the open block being written
+fn compressed(dir: &std::path::Path, durability: Durability) -> LogFile {
+  let mut log = init(
+    dir,
+    Options {
+      compression: Compression::Zstd,
+      ..Options::default()
+    },
+  );
+  log.set_durability(durability);
+  log
+}
+
+/// Entries written after each single append
+fn written(log: &mut LogFile, appends: usize) -> Vec<usize> {
+  (0..appends)
+    .map(|i| {
+      log.add_entry(entry(i)).unwrap();
+      log.index.count()
+    })
+    .collect()
+}
+
+#[test]
+fn never_synced() {
+  let dir = tempfile::tempdir().unwrap();
+  let mut log = compressed(dir.path(), Durability::None);
+  assert_eq!(written(&mut log, 4), vec![0, 0, 0, 0]);
+}
+
+#[test]
+fn synced_every_n() {
+  let dir = tempfile::tempdir().unwrap();
+  let mut log = compressed(dir.path(), Durability::EveryN(3));
+  assert_eq!(written(&mut log, 7), vec![0, 0, 3, 3, 3, 6, 6]);
+
+  // A batch counts with all of its entries
+  let batch: Vec<_> = (7..9).map(entry).collect();
+  log.add_entries(&batch).unwrap();
+  assert_eq!(log.index.count(), 9);
+}
+
+#[test]
+fn synced_on_interval() {
+  let dir = tempfile::tempdir().unwrap();
+  let mut log = compressed(dir.path(), Durability::Interval(Duration::from_millis(500)));
+  assert_eq!(written(&mut log, 2), vec![0, 0]);
+  std::thread::sleep(Duration::from_millis(600));
+  log.add_entry(entry(2)).unwrap();
+  assert_eq!(log.index.count(), 3);
+}
+
+#[test]
+fn synced_always() {
+  let dir = tempfile::tempdir().unwrap();
+  let mut log = compressed(dir.path(), Durability::Always);
+  assert_eq!(written(&mut log, 4), vec![1, 2, 3, 4]);
+}
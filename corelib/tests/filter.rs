@@ -0,0 +1,185 @@
+mod common;
+
+use chrono::{TimeZone, Utc};
+use common::{init, text_entry};
+use corelib::fs::{
+  Clock, Compression, Entry, Fields, Filter, KeyFilter, LogFile, Options, Severity, TimeRange,
+};
+use std::collections::BTreeMap;
+
+/// Entries of several segments with different senders, units,
+/// severities and log formats
+///
+/// Each sender writes stretches of 1500 entries, sender "rare" and
+/// unit "cron" show up in a single segment only.
+fn entry(i: usize) -> Entry {
+  let sender = if (7000..7005).contains(&i) {
+    "rare".to_string()
+  } else {
+    format!("host{}", (i / 1500) % 4)
+  };
+  let severity = match i % 10 {
+    0 => Severity::Error,
+    5 => Severity::Warning,
+    _ => Severity::Info,
+  };
+  let unit = if (4000..4100).contains(&i) {
+    "cron".to_string()
+  } else {
+    format!("unit{}", i % 3)
+  };
+  let mut extra = BTreeMap::new();
+  if i.is_multiple_of(200) {
+    extra.insert("user".to_string(), "alice".to_string());
+  }
+  Entry {
+    sender,
+    received: Utc.timestamp_opt(1000 + i as i64, 0).unwrap(),
+    log_format: if i.is_multiple_of(50) { 2 } else { 1 },
+    fields: Some(Fields {
+      severity: Some(severity),
+      unit: Some(unit),
+      extra,
+      ..Fields::default()
+    }),
+    ..text_entry(format!("log entry {i}"))
+  }
+}
+
+fn keys(senders: &[&str], units: &[&str], severities: &[Severity]) -> KeyFilter {
+  KeyFilter {
+    senders: senders.iter().map(|s| s.to_string()).collect(),
+    units: units.iter().map(|u| u.to_string()).collect(),
+    severities: severities.to_vec(),
+  }
+}
+
+fn filters() -> Vec<Filter> {
+  let after = TimeRange::new(Clock::Received).after(Utc.timestamp_opt(3000, 0).unwrap());
+  vec![
+    Filter::new(),
+    Filter::new().sender("rare"),
+    Filter::new().sender("nobody"),
+    Filter {
+      keys: keys(&["host1", "rare"], &[], &[Severity::Error]),
+      ..Filter::new()
+    },
+    Filter {
+      keys: keys(&["host2"], &["cron"], &[]),
+      ..Filter::new()
+    },
+    Filter {
+      keys: keys(&[], &["unit2"], &[Severity::Warning, Severity::Error]),
+      ..Filter::new().range(after)
+    },
+    Filter::new()
+      .sender("host3")
+      .min_severity(Severity::Warning),
+    Filter::new().min_severity(Severity::Error).log_format(2),
+    Filter::new()
+      .field("unit", "cron")
+      .min_severity(Severity::Warning),
+    Filter::new().field("user", "alice").sender("host0"),
+    Filter::new()
+      .regex(r"^log entry 70\d\d$")
+      .unwrap()
+      .sender("rare"),
+    Filter::new().log_format(2).range(after),
+  ]
+}
+
+/// Every filter finds the same entries as checking all of them
+fn check(log: &LogFile, entries: &[Entry]) {
+  for filter in filters() {
+    let found: Vec<String> = log
+      .iter_filter(filter.clone())
+      .unwrap()
+      .map(|entry| entry.unwrap().log_entry)
+      .collect();
+    let expected: Vec<String> = entries
+      .iter()
+      .filter(|entry| filter.matches(entry))
+      .map(|entry| entry.log_entry.clone())
+      .collect();
+    assert_eq!(found, expected, "{filter:?}");
+
+    // Resumed after the entries a client already has
+    for n in [1, 7001, 12_000] {
+      let found: Vec<String> = log
+        .iter_filter_from(n, filter.clone())
+        .unwrap()
+        .map(|entry| entry.unwrap().log_entry)
+        .collect();
+      let expected: Vec<String> = entries[n..]
+        .iter()
+        .filter(|entry| filter.matches(entry))
+        .map(|entry| entry.log_entry.clone())
+        .collect();
+      assert_eq!(found, expected, "{n} {filter:?}");
+    }
+  }
+}
+
+fn sealed(compression: Compression) {
+  let dir = tempfile::tempdir().unwrap();
+  let mut log = init(
+    dir.path(),
+    Options {
+      compression,
+      ..Options::default()
+    },
+  );
+  let entries: Vec<Entry> = (0..12_000).map(entry).collect();
+  for chunk in entries.chunks(400) {
+    log.add_entries(chunk).unwrap();
+  }
+  log.close().unwrap();
+  check(&log, &entries);
+  let offset = log.seek_to_count(2000).unwrap();
+  drop(log);
+
+  let log = LogFile::open_readonly(common::path(dir.path())).unwrap();
+  check(&log, &entries);
+  drop(log);
+
+  // Damage a record far from the entries of "rare", its segment
+  // is skipped by the bloom filter and never read
+  let path = common::path(dir.path());
+  let mut bytes = std::fs::read(&path).unwrap();
+  bytes[offset as usize + 12] ^= 0xff;
+  std::fs::write(&path, bytes).unwrap();
+  let log = LogFile::open_readonly(&path).unwrap();
+  let found: Vec<_> = log
+    .iter_filter(Filter::new().sender("rare"))
+    .unwrap()
+    .collect();
+  assert_eq!(found.len(), 5);
+  assert!(found.iter().all(|entry| entry.is_ok()));
+}
+
+#[test]
+fn sealed_file() {
+  sealed(Compression::None);
+}
+
+#[test]
+fn sealed_compressed_file() {
+  sealed(Compression::Zstd);
+}
+
+#[test]
+fn live_file() {
+  let dir = tempfile::tempdir().unwrap();
+  let mut log = init(dir.path(), Options::default());
+  let entries: Vec<Entry> = (0..12_000).map(entry).collect();
+  // The saved sparse index covers the first half only, a reader
+  // sees the rest as an unindexed tail
+  log.add_entries(&entries[..6000]).unwrap();
+  log.reindex().unwrap();
+  log.add_entries(&entries[6000..]).unwrap();
+  log.sync().unwrap();
+  check(&log, &entries);
+
+  let reader = LogFile::open_readonly(common::path(dir.path())).unwrap();
+  check(&reader, &entries);
+}
@@ -0,0 +1,134 @@
+mod common;
+
+use chrono::{TimeZone, Utc};
+use common::text_entry;
+use corelib::format::{LogFormat, Parser, Registry, TextParser};
+use corelib::fs::{Entry, Fields, Severity};
+
+fn parse(format: LogFormat, log_entry: &str) -> Option<Fields> {
+  Registry::default().parse(format.code(), log_entry)
+}
+
+#[test]
+fn journald() {
+  let fields = parse(
+    LogFormat::Journald,
+    r#"{"PRIORITY":"3","_HOSTNAME":"h1","_SYSTEMD_UNIT":"nginx.service","_PID":"42","MESSAGE":"boom","_BOOT_ID":"x","MESSAGE_ID":[1,2],"__REALTIME_TIMESTAMP":"1700000000000000"}"#,
+  )
+  .unwrap();
+
+  assert_eq!(fields.severity, Some(Severity::Error));
+  assert_eq!(fields.hostname.as_deref(), Some("h1"));
+  assert_eq!(fields.unit.as_deref(), Some("nginx.service"));
+  assert_eq!(fields.pid, Some(42));
+  assert_eq!(fields.message.as_deref(), Some("boom"));
+  assert_eq!(
+    fields.timestamp,
+    Utc.timestamp_opt(1_700_000_000, 0).single()
+  );
+  assert_eq!(fields.extra["_BOOT_ID"], "x");
+  assert_eq!(fields.extra["MESSAGE_ID"], "[1,2]");
+  assert!(parse(LogFormat::Journald, "not json").is_none());
+}
+
+#[test]
+fn syslog() {
+  let fields = parse(
+    LogFormat::Syslog,
+    r#"<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 [exampleSDID@32473 iut="3" eventSource="App\"lication"][x a="b"] An application event"#,
+  )
+  .unwrap();
+
+  assert_eq!(fields.severity, Some(Severity::Notice));
+  assert_eq!(fields.hostname.as_deref(), Some("mymachine.example.com"));
+  assert_eq!(fields.unit.as_deref(), Some("evntslog"));
+  assert_eq!(fields.pid, None);
+  assert_eq!(fields.message.as_deref(), Some("An application event"));
+  assert!(fields.timestamp.is_some());
+  assert_eq!(fields.extra["facility"], "20");
+  assert_eq!(fields.extra["msgid"], "ID47");
+  assert_eq!(
+    fields.extra["exampleSDID@32473.eventSource"],
+    "App\"lication"
+  );
+  assert_eq!(fields.extra["x.a"], "b");
+
+  let fields = parse(LogFormat::Syslog, "<34>1 - host su 77 - - 'su root' failed").unwrap();
+  assert_eq!(fields.severity, Some(Severity::Critical));
+  assert_eq!(fields.pid, Some(77));
+  assert_eq!(fields.message.as_deref(), Some("'su root' failed"));
+  assert_eq!(fields.timestamp, None);
+  assert!(parse(LogFormat::Syslog, "<34> bad").is_none());
+  assert!(parse(LogFormat::Syslog, "hello").is_none());
+}
+
+#[test]
+fn logfmt() {
+  let fields = parse(
+    LogFormat::Logfmt,
+    r#"level=warn msg="disk \"almost\" full" host=db1 pid=9 user=42 dry"#,
+  )
+  .unwrap();
+
+  assert_eq!(fields.severity, Some(Severity::Warning));
+  assert_eq!(fields.message.as_deref(), Some("disk \"almost\" full"));
+  assert_eq!(fields.hostname.as_deref(), Some("db1"));
+  assert_eq!(fields.pid, Some(9));
+  assert_eq!(fields.extra["user"], "42");
+  assert_eq!(fields.extra["dry"], "");
+  assert!(parse(LogFormat::Logfmt, r#"msg="open"#).is_none());
+}
+
+#[test]
+fn json() {
+  let fields = parse(
+    LogFormat::Json,
+    r#"{"level":"ERROR","message":"x","service":"api","n":1,"ts":"2023-11-14T22:13:20Z"}"#,
+  )
+  .unwrap();
+
+  assert_eq!(fields.severity, Some(Severity::Error));
+  assert_eq!(fields.message.as_deref(), Some("x"));
+  assert_eq!(fields.unit.as_deref(), Some("api"));
+  assert_eq!(fields.extra["n"], "1");
+  assert_eq!(
+    fields.timestamp,
+    Utc.timestamp_opt(1_700_000_000, 0).single()
+  );
+  assert!(parse(LogFormat::Json, "[1, 2]").is_none());
+}
+
+#[test]
+fn text_is_not_parsed_by_default() {
+  assert!(parse(LogFormat::Text, "plain line\n").is_none());
+
+  let fields = TextParser.parse("plain line\n").unwrap();
+  assert_eq!(fields.message.as_deref(), Some("plain line"));
+}
+
+struct Upper;
+
+impl Parser for Upper {
+  fn parse(&self, log_entry: &str) -> Option<Fields> {
+    Some(Fields {
+      message: Some(log_entry.to_uppercase()),
+      ..Fields::default()
+    })
+  }
+}
+
+#[test]
+fn custom_format() {
+  let mut registry = Registry::default();
+  registry.register(100, Upper);
+  let mut entry = Entry {
+    log_format: 100,
+    ..text_entry("hi".to_string())
+  };
+
+  registry.parse_entry(&mut entry);
+  assert_eq!(entry.fields.unwrap().message.as_deref(), Some("HI"));
+  assert!(registry.parse(99, "x").is_none());
+  assert_eq!(LogFormat::from_code(2), Some(LogFormat::Syslog));
+  assert_eq!(i32::from(LogFormat::Json), 4);
+}
@@ -0,0 +1,29 @@
+use corelib::fs::{migrate, LogFile};
+use corelib::Error;
+
+#[test]
+fn missing_file_leaves_no_lock() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = dir.path().join("missing.towl");
+
+  assert!(matches!(LogFile::open(&path), Err(Error::Io(_))));
+  assert!(matches!(migrate(&path), Err(Error::Io(_))));
+  assert!(!dir.path().join("missing.towl.lock").exists());
+}
+
+#[test]
+fn new_file_stays_locked() {
+  let dir = tempfile::tempdir().unwrap();
+  let log = LogFile::init(
+    dir.path().to_str().unwrap(),
+    "org".to_string(),
+    "title".to_string(),
+    1,
+  )
+  .unwrap();
+
+  let path = dir.path().join("1.towl");
+  assert!(matches!(LogFile::open(&path), Err(Error::Locked(_))));
+  drop(log);
+  assert!(LogFile::open(&path).is_ok());
+}
@@ -0,0 +1,144 @@
+mod common;
+
+use common::text_entry;
+use corelib::fs::{Clock, Entry, Filter};
+use corelib::logger::{Logger, CATALOG_EVERY, DATA_PATH};
+use corelib::store::{FileState, LogStore};
+use tokio::sync::Mutex;
+
+/// The logger works in the current directory, tests take turns
+static CWD: Mutex<()> = Mutex::const_new(());
+
+fn entry(i: usize) -> Entry {
+  Entry {
+    log_format: 4,
+    ..text_entry(format!(
+      "{{\"msg\":\"log entry {i}\",\"level\":\"warning\"}}"
+    ))
+  }
+}
+
+fn seqs() -> Vec<u64> {
+  let store = LogStore::open_readonly(DATA_PATH).unwrap();
+  store
+    .query(Filter::new())
+    .map(|entry| entry.unwrap().seq)
+    .collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn numbering_continues_across_archive() {
+  let _cwd = CWD.lock().await;
+  let dir = tempfile::tempdir().unwrap();
+  std::env::set_current_dir(dir.path()).unwrap();
+
+  let logger = Logger::init("org".to_string(), "title".to_string(), 10)
+    .await
+    .unwrap();
+  let mut rx = logger.watch();
+  for i in 0..3 {
+    logger.add_entry(entry(i)).await.unwrap();
+  }
+  let first = rx.recv().await.unwrap();
+  assert_eq!(first.seq, 1);
+  assert!(first.fields.is_some());
+  logger.archive().await.unwrap();
+  for i in 3..5 {
+    logger.add_entry(entry(i)).await.unwrap();
+  }
+
+  let store = LogStore::open_readonly(DATA_PATH).unwrap();
+  let states: Vec<_> = store.files().map(|info| (info.id, info.state)).collect();
+  assert_eq!(
+    states,
+    vec![(1, FileState::Archived), (2, FileState::Working)]
+  );
+  assert_eq!(seqs(), vec![1, 2, 3, 4, 5]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn numbering_survives_crash_during_archive() {
+  let _cwd = CWD.lock().await;
+  let dir = tempfile::tempdir().unwrap();
+  std::env::set_current_dir(dir.path()).unwrap();
+
+  // Settings of older releases, saved before the crash
+  let settings = [10i32.to_le_bytes(), 1i32.to_le_bytes()].concat();
+  std::fs::write("settings", settings).unwrap();
+  // Crash after the new working file was created
+  let mut store = LogStore::open(DATA_PATH).unwrap();
+  let mut sealed = store
+    .create("org".to_string(), "title".to_string())
+    .unwrap();
+  for i in 1..=3 {
+    sealed
+      .add_entry(Entry {
+        seq: i as u64,
+        ..entry(i)
+      })
+      .unwrap();
+  }
+  store.seal(&mut sealed).unwrap();
+  drop(
+    store
+      .create("org".to_string(), "title".to_string())
+      .unwrap(),
+  );
+  drop(sealed);
+  drop(store);
+
+  let logger = Logger::init("org".to_string(), "title".to_string(), 10)
+    .await
+    .unwrap();
+  logger.add_entry(entry(4)).await.unwrap();
+  assert_eq!(seqs(), vec![1, 2, 3, 4]);
+}
+
+/// Index of the working file as listed in the catalog
+fn listed_working() -> (usize, bool) {
+  let store = LogStore::open_readonly(DATA_PATH).unwrap();
+  let info = store.working().unwrap();
+  let first = info.index.first_date(Clock::Received);
+  (info.index.count(), first.is_some())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn catalog_follows_working_file() {
+  let _cwd = CWD.lock().await;
+  let dir = tempfile::tempdir().unwrap();
+  std::env::set_current_dir(dir.path()).unwrap();
+
+  let logger = Logger::init("org".to_string(), "title".to_string(), 10)
+    .await
+    .unwrap();
+  for i in 0..CATALOG_EVERY - 1 {
+    logger.add_entry(entry(i)).await.unwrap();
+  }
+  assert_eq!(listed_working(), (0, false));
+  logger.add_entry(entry(CATALOG_EVERY - 1)).await.unwrap();
+  assert_eq!(listed_working(), (CATALOG_EVERY, true));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn catalog_is_updated_on_start() {
+  let _cwd = CWD.lock().await;
+  let dir = tempfile::tempdir().unwrap();
+  std::env::set_current_dir(dir.path()).unwrap();
+
+  // Entries the catalog did not list before a crash
+  let mut store = LogStore::open(DATA_PATH).unwrap();
+  let mut working = store
+    .create("org".to_string(), "title".to_string())
+    .unwrap();
+  for i in 0..5 {
+    working.add_entry(entry(i)).unwrap();
+  }
+  drop(working);
+  drop(store);
+  assert_eq!(listed_working(), (0, false));
+
+  let _logger = Logger::init("org".to_string(), "title".to_string(), 10)
+    .await
+    .unwrap();
+  assert_eq!(listed_working(), (5, true));
+}
@@ -0,0 +1,218 @@
+mod common;
+
+use chrono::{TimeZone, Utc};
+use common::text_entry;
+use corelib::fs::{Clock, Entry, Fields, Filter, LogFile, TimeRange};
+use corelib::store::LogStore;
+use std::path::Path;
+
+/// Entry received at `t` with source time `source`, its seq is `t`
+fn entry(t: i64, source: i64) -> Entry {
+  Entry {
+    sender: if t % 7 == 0 { "x" } else { "y" }.to_string(),
+    received: Utc.timestamp_opt(t, 0).unwrap(),
+    fields: Some(Fields {
+      timestamp: Utc.timestamp_opt(source, 0).single(),
+      ..Fields::default()
+    }),
+    seq: t as u64,
+    ..text_entry(format!("entry {t}"))
+  }
+}
+
+/// Store with one sealed file per batch
+fn store(dir: &Path, files: &[Vec<Entry>]) -> LogStore {
+  let mut store = LogStore::open(dir).unwrap();
+  for entries in files {
+    let mut log = store
+      .create("org".to_string(), "title".to_string())
+      .unwrap();
+    log.add_entries(entries).unwrap();
+    store.seal(&mut log).unwrap();
+  }
+  LogStore::open_readonly(dir).unwrap()
+}
+
+fn seqs(store: &LogStore, filter: Filter) -> Vec<u64> {
+  store
+    .query(filter)
+    .map(|entry| entry.unwrap().seq)
+    .collect()
+}
+
+fn expected(files: &[Vec<Entry>], filter: &Filter) -> Vec<u64> {
+  let clock = filter.range.clock;
+  let mut res: Vec<&Entry> = files
+    .iter()
+    .flatten()
+    .filter(|e| filter.matches(e))
+    .collect();
+  res.sort_by_key(|entry| (entry.time(clock), entry.seq));
+  res.iter().map(|entry| entry.seq).collect()
+}
+
+/// Overlapping files, each with several records
+fn overlapping() -> Vec<Vec<Entry>> {
+  (0..7)
+    .map(|k| {
+      (0..600)
+        .map(|i| {
+          let t = k * 500 + i * 7 + k;
+          entry(t, 100_000 - t)
+        })
+        .collect()
+    })
+    .collect()
+}
+
+#[test]
+fn merged_by_received_time() {
+  let dir = tempfile::tempdir().unwrap();
+  let files = overlapping();
+  let store = store(dir.path(), &files);
+
+  let all = Filter::new();
+  assert_eq!(seqs(&store, all.clone()), expected(&files, &all));
+  assert_eq!(seqs(&store, all).len(), 7 * 600);
+
+  let range = TimeRange::new(Clock::Received)
+    .after(Utc.timestamp_opt(1200, 0).unwrap())
+    .before(Utc.timestamp_opt(2100, 0).unwrap());
+  let filter = Filter::new().range(range).sender("x");
+  let found = seqs(&store, filter.clone());
+  assert!(!found.is_empty());
+  assert_eq!(found, expected(&files, &filter));
+}
+
+#[test]
+fn merged_by_source_time() {
+  let dir = tempfile::tempdir().unwrap();
+  let files = overlapping();
+  let store = store(dir.path(), &files);
+
+  // Source times run backwards in every file
+  let filter = Filter::new().range(TimeRange::new(Clock::Source));
+  let found = seqs(&store, filter.clone());
+  assert_eq!(found.len(), 7 * 600);
+  assert_eq!(found, expected(&files, &filter));
+}
+
+#[test]
+fn clock_going_backwards() {
+  let dir = tempfile::tempdir().unwrap();
+  // The second file was written while the clock jumped back
+  let files = vec![
+    (0..1000).map(|t| entry(t * 2, 0)).collect(),
+    (0..500)
+      .chain(300..800)
+      .map(|t| entry(t * 2 + 1, 0))
+      .collect::<Vec<_>>(),
+  ];
+  let store = store(dir.path(), &files);
+
+  let filter = Filter::new();
+  let found: Vec<_> = store
+    .query(filter.clone())
+    .map(|entry| entry.unwrap().received)
+    .collect();
+  assert_eq!(found.len(), 2000);
+  assert!(found.windows(2).all(|w| w[0] <= w[1]));
+  assert_eq!(seqs(&store, filter.clone()), expected(&files, &filter));
+}
+
+#[test]
+fn broken_file_is_reported() {
+  let dir = tempfile::tempdir().unwrap();
+  let files = overlapping();
+  let store = store(dir.path(), &files);
+  std::fs::write(dir.path().join("2.towl"), b"towlfile*garbage").unwrap();
+
+  let found: Vec<_> = store.query(Filter::new()).collect();
+  assert_eq!(found.iter().filter(|e| e.is_err()).count(), 1);
+  let seqs: Vec<_> = found
+    .into_iter()
+    .filter_map(Result::ok)
+    .map(|e| e.seq)
+    .collect();
+  let mut rest = files;
+  rest.remove(1);
+  assert_eq!(seqs, expected(&rest, &Filter::new()));
+}
+
+#[test]
+fn files_are_opened_when_reached() {
+  let dir = tempfile::tempdir().unwrap();
+  let files: Vec<Vec<Entry>> = (0..3)
+    .map(|k| (0..600).map(|i| entry(k * 1000 + i, 0)).collect())
+    .collect();
+  let store = store(dir.path(), &files);
+  std::fs::write(dir.path().join("3.towl"), b"towlfile*garbage").unwrap();
+
+  // The broken last file is only opened after the others
+  let found: Vec<_> = store.query(Filter::new()).take(1200).collect();
+  assert!(found.iter().all(|entry| entry.is_ok()));
+  assert!(store.query(Filter::new()).any(|entry| entry.is_err()));
+}
+
+/// A file of `segments` sparse index segments, received in order,
+/// the source times of each segment within its own window of times
+/// running backwards
+fn backwards(segments: i64) -> Vec<Entry> {
+  (0..segments * 1024)
+    .map(|t| entry(t, 10_000_000 - (t / 1024) * 2000 - (t * 7) % 1500))
+    .collect()
+}
+
+#[test]
+fn large_file_by_source_time() {
+  let dir = tempfile::tempdir().unwrap();
+  let files = vec![backwards(20)];
+  let store = store(dir.path(), &files);
+
+  let filter = Filter::new().range(TimeRange::new(Clock::Source));
+  assert_eq!(seqs(&store, filter.clone()), expected(&files, &filter));
+  let range = TimeRange::new(Clock::Source)
+    .after(Utc.timestamp_opt(9_950_000, 0).unwrap())
+    .before(Utc.timestamp_opt(9_990_000, 0).unwrap());
+  let filter = Filter::new().range(range).sender("x");
+  let found = seqs(&store, filter.clone());
+  assert!(!found.is_empty());
+  assert_eq!(found, expected(&files, &filter));
+
+  // Segments are read as the merge reaches them: the entries before
+  // a damaged one come without an error
+  let path = dir.path().join("1.towl");
+  let offset = LogFile::open_readonly(&path)
+    .unwrap()
+    .seek_to_count(1024 + 512)
+    .unwrap();
+  let mut bytes = std::fs::read(&path).unwrap();
+  bytes[offset as usize + 12] ^= 0xff;
+  std::fs::write(&path, bytes).unwrap();
+  let filter = Filter::new().range(TimeRange::new(Clock::Source));
+  let found: Vec<_> = store.query(filter.clone()).collect();
+  let first_error = found.iter().position(|entry| entry.is_err()).unwrap();
+  assert!(first_error >= 18 * 1024);
+}
+
+#[test]
+fn large_file_with_clock_going_backwards() {
+  let dir = tempfile::tempdir().unwrap();
+  // Every fifth segment was received an hour earlier
+  let files = vec![(0..20 * 1024)
+    .map(|t| match (t / 1024) % 5 {
+      4 => entry(t, 0),
+      _ => entry(t + 3600, 0),
+    })
+    .collect::<Vec<_>>()];
+  let store = store(dir.path(), &files);
+
+  let filter = Filter::new();
+  let found: Vec<_> = store
+    .query(filter.clone())
+    .map(|entry| entry.unwrap().received)
+    .collect();
+  assert_eq!(found.len(), 20 * 1024);
+  assert!(found.windows(2).all(|w| w[0] <= w[1]));
+  assert_eq!(seqs(&store, filter.clone()), expected(&files, &filter));
+}
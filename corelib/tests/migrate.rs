@@ -0,0 +1,171 @@
+mod common;
+
+use chrono::{DateTime, Utc};
+use common::{entry, init, messages};
+use corelib::fs::{
+  migrate, migrate_to, migrate_to_with, Cipher, Encryption, Key, KeyRing, LogFile, Options,
+};
+use corelib::Error;
+use serde::Serialize;
+use std::path::Path;
+
+/// Header of version 1 and 2 files
+#[derive(Serialize)]
+struct OldHeader {
+  magic: [u8; 9],
+  version: i32,
+  org: String,
+  title: String,
+  id: usize,
+}
+
+/// Index of version 1 and 2 files
+#[derive(Serialize)]
+struct OldIndex {
+  opened: DateTime<Utc>,
+  closed: Option<DateTime<Utc>>,
+  count: usize,
+  first: Option<DateTime<Utc>>,
+  last: Option<DateTime<Utc>>,
+}
+
+/// Entry of versions before structured fields
+#[derive(Serialize)]
+struct OldEntry {
+  sender: String,
+  received: DateTime<Utc>,
+  log_format: i32,
+  log_entry: String,
+}
+
+/// Write a version 1 (bare) or 2 (framed) file with `count` entries
+fn write_old(path: &Path, version: i32, count: usize) {
+  let header = OldHeader {
+    magic: *b"towlfile*",
+    version,
+    org: "org".to_string(),
+    title: "title".to_string(),
+    id: 7,
+  };
+  let index = OldIndex {
+    opened: Utc::now(),
+    closed: Some(Utc::now()),
+    count,
+    first: None,
+    last: None,
+  };
+  let mut buf = bincode::serialize(&header).unwrap();
+  buf.resize(1024, 0);
+  buf.extend(bincode::serialize(&index).unwrap());
+  buf.resize(2048, 0);
+  for i in 0..count {
+    let payload = bincode::serialize(&OldEntry {
+      sender: "test".to_string(),
+      received: Utc::now(),
+      log_format: 0,
+      log_entry: format!("log entry {i}"),
+    })
+    .unwrap();
+    if version == 2 {
+      buf.extend((payload.len() as u32).to_le_bytes());
+      buf.extend(crc32fast::hash(&payload).to_le_bytes());
+    }
+    buf.extend(payload);
+  }
+  std::fs::write(path, buf).unwrap();
+}
+
+#[test]
+fn v1_file_is_readable() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = dir.path().join("7.towl");
+  write_old(&path, 1, 3000);
+
+  let log = LogFile::open_readonly(&path).unwrap();
+  assert_eq!(log.header.version(), 1);
+  assert_eq!(log.index.count(), 3000);
+  assert_eq!(messages(&log)[2999], "log entry 2999");
+  let tail: Vec<_> = log.tail(2).unwrap().map(|e| e.unwrap().log_entry).collect();
+  assert_eq!(tail, vec!["log entry 2998", "log entry 2999"]);
+  let last = log.iter_rev().unwrap().next().unwrap().unwrap();
+  assert_eq!(last.log_entry, "log entry 2999");
+}
+
+#[test]
+fn old_file_needs_migrate_for_writing() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = dir.path().join("7.towl");
+  write_old(&path, 1, 3);
+
+  assert!(matches!(LogFile::open(&path), Err(Error::NeedsMigrate(1))));
+
+  // An open reader keeps the file from being replaced
+  let reader = LogFile::open_readonly(&path).unwrap();
+  assert!(matches!(migrate(&path), Err(Error::Locked(_))));
+  drop(reader);
+
+  let report = migrate(&path).unwrap();
+  assert_eq!((report.from, report.entries), (1, 3));
+  let mut log = LogFile::open(&path).unwrap();
+  assert!(log.recovery().is_clean());
+  log.add_entry(entry(3)).unwrap();
+  assert_eq!(messages(&log).len(), 4);
+  assert_eq!(messages(&log)[0], "log entry 0");
+  drop(log);
+
+  // Current files are left untouched
+  assert_eq!(migrate(&path).unwrap().from, log_version(&path));
+  assert!(!dir.path().join("7.towl.migrate").exists());
+  assert!(!dir.path().join("7.towl.migrate.lock").exists());
+}
+
+fn log_version(path: &Path) -> i32 {
+  LogFile::open_readonly(path).unwrap().header.version()
+}
+
+#[test]
+fn v2_file_is_copied() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = dir.path().join("7.towl");
+  let target = dir.path().join("8.towl");
+  write_old(&path, 2, 10);
+
+  let report = migrate_to(&path, &target).unwrap();
+  assert_eq!((report.from, report.entries), (2, 10));
+  assert_eq!(log_version(&path), 2);
+  let log = LogFile::open_readonly(&target).unwrap();
+  assert_eq!(log.index.count(), 10);
+  assert_eq!(messages(&log).len(), 10);
+}
+
+#[test]
+fn encrypted_file_stays_encrypted() {
+  let dir = tempfile::tempdir().unwrap();
+  let key = Key::new("k1".to_string(), [7; 32]);
+  let options = Options {
+    encryption: Some(Encryption {
+      cipher: Cipher::ChaCha20Poly1305,
+      key: key.clone(),
+    }),
+    ..Options::default()
+  };
+  let mut log = init(dir.path(), options);
+  log.add_entry(entry(0)).unwrap();
+  drop(log);
+
+  let path = common::path(dir.path());
+  let target = dir.path().join("2.towl");
+  let keys = KeyRing::from(key);
+  assert!(matches!(
+    migrate_to(&path, &target),
+    Err(Error::MissingKey(_))
+  ));
+  migrate_to_with(&path, &target, &keys).unwrap();
+
+  assert!(matches!(
+    LogFile::open_readonly(&target),
+    Err(Error::MissingKey(_))
+  ));
+  let log = LogFile::open_readonly_with(&target, &keys).unwrap();
+  assert_eq!(messages(&log), vec!["log entry 0"]);
+}
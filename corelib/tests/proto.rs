@@ -0,0 +1,65 @@
+use chrono::{TimeZone, Utc};
+use corelib::fs::{Entry, Fields, Severity};
+
+fn fields() -> Fields {
+  let mut fields = Fields {
+    severity: Some(Severity::Warning),
+    hostname: Some("host".to_string()),
+    unit: Some("nginx.service".to_string()),
+    pid: Some(42),
+    message: Some("message".to_string()),
+    timestamp: Some(Utc.timestamp_opt(1_700_000_000, 0).unwrap()),
+    ..Fields::default()
+  };
+  fields.extra.insert("key".to_string(), "value".to_string());
+  fields
+}
+
+#[test]
+fn fields_round_trip() {
+  let sent = proto::towl::Fields::from(fields());
+  assert_eq!(sent.severity, Some(4));
+  assert_eq!(
+    sent.timestamp_rfc3339.as_deref(),
+    Some("2023-11-14T22:13:20+00:00")
+  );
+  assert_eq!(Fields::from(sent), fields());
+  assert_eq!(
+    Fields::from(proto::towl::Fields::default()),
+    Fields::default()
+  );
+}
+
+#[test]
+fn invalid_fields_are_unset() {
+  let received = proto::towl::Fields {
+    severity: Some(8),
+    timestamp_rfc3339: Some("yesterday".to_string()),
+    ..proto::towl::Fields::from(fields())
+  };
+
+  let fields = Fields::from(received);
+  assert_eq!(fields.severity, None);
+  assert_eq!(fields.timestamp, None);
+  assert_eq!(fields.pid, Some(42));
+}
+
+#[test]
+fn entry_is_sent_with_fields_and_seq() {
+  let entry = Entry {
+    sender: "test".to_string(),
+    received: Utc.timestamp_opt(1_700_000_000, 0).unwrap(),
+    log_format: 1,
+    log_entry: "log entry".to_string(),
+    fields: Some(fields()),
+    seq: 47,
+  };
+
+  let sent = proto::towl::Entry::from(entry);
+  assert_eq!(sent.sender, "test");
+  assert_eq!(sent.received_rfc3339, "2023-11-14T22:13:20+00:00");
+  assert_eq!(sent.log_format, 1);
+  assert_eq!(sent.log_entry, "log entry");
+  assert_eq!(sent.fields.map(Fields::from), Some(fields()));
+  assert_eq!(sent.seq, 47);
+}
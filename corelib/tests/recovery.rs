@@ -0,0 +1,268 @@
+mod common;
+
+use common::{entry, init, messages, text_entry};
+use corelib::fs::{Compression, Entry, LogFile, Options};
+use corelib::Error;
+use std::fs::OpenOptions;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Create a log file with `count` entries and return its path
+fn create(dir: &Path, count: usize) -> PathBuf {
+  let mut log = init(dir, Options::default());
+  for i in 0..count {
+    log.add_entry(entry(i)).unwrap();
+  }
+  common::path(dir)
+}
+
+/// Simulate a process killed in the middle of writing a record
+fn append_torn_record(path: &Path) -> u64 {
+  let mut file = OpenOptions::new().append(true).open(path).unwrap();
+  let len = file.metadata().unwrap().len();
+  // Frame header promises 100 bytes, but only 20 were written
+  file.write_all(&100u32.to_le_bytes()).unwrap();
+  file.write_all(&0u32.to_le_bytes()).unwrap();
+  file.write_all(&[7u8; 20]).unwrap();
+  len
+}
+
+fn file_len(path: &Path) -> u64 {
+  std::fs::metadata(path).unwrap().len()
+}
+
+#[test]
+fn torn_tail_is_truncated_and_quarantined() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = create(dir.path(), 10);
+  let valid_len = append_torn_record(&path);
+
+  let log = LogFile::open(&path).unwrap();
+
+  assert_eq!(log.index.count(), 10);
+  assert_eq!(log.recovery().tail, Some(valid_len));
+  assert_eq!(log.recovery().quarantined, 28);
+  assert_eq!(file_len(&path), valid_len);
+  assert_eq!(file_len(&dir.path().join("1.towl.corrupt")), 28);
+}
+
+#[test]
+fn torn_frame_header_is_truncated() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = create(dir.path(), 3);
+  let valid_len = file_len(&path);
+  OpenOptions::new()
+    .append(true)
+    .open(&path)
+    .unwrap()
+    .write_all(&[1, 2, 3])
+    .unwrap();
+
+  let log = LogFile::open(&path).unwrap();
+
+  assert_eq!(log.index.count(), 3);
+  assert_eq!(log.recovery().tail, Some(valid_len));
+  assert_eq!(file_len(&path), valid_len);
+}
+
+#[test]
+fn entries_added_after_recovery_are_readable() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = create(dir.path(), 5);
+  append_torn_record(&path);
+
+  {
+    let mut log = LogFile::open(&path).unwrap();
+    log.add_entry(entry(5)).unwrap();
+    log.add_entry(entry(6)).unwrap();
+  }
+
+  let log = LogFile::open(&path).unwrap();
+  assert_eq!(log.index.count(), 7);
+  assert!(log.recovery().is_clean());
+}
+
+#[test]
+fn damaged_record_is_skipped_not_truncated() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = create(dir.path(), 4);
+  let len = file_len(&path);
+
+  // Flip the last payload byte of the last record
+  let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+  file.seek(SeekFrom::Start(len - 1)).unwrap();
+  file.write_all(&[0xff]).unwrap();
+  drop(file);
+
+  let log = LogFile::open(&path).unwrap();
+
+  assert_eq!(log.index.count(), 3);
+  assert_eq!(log.recovery().damaged.len(), 1);
+  assert_eq!(log.recovery().tail, None);
+  assert_eq!(file_len(&path), len);
+}
+
+#[test]
+fn clean_file_needs_no_recovery() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = create(dir.path(), 8);
+
+  let log = LogFile::open(&path).unwrap();
+
+  assert_eq!(log.index.count(), 8);
+  assert!(log.recovery().is_clean());
+  assert!(!dir.path().join("1.towl.corrupt").exists());
+}
+
+#[test]
+fn zero_filled_tail_is_truncated() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = create(dir.path(), 6);
+  let valid_len = file_len(&path);
+
+  // File size was extended by the crash, but the data never made it
+  OpenOptions::new()
+    .append(true)
+    .open(&path)
+    .unwrap()
+    .write_all(&[0; 4096])
+    .unwrap();
+
+  let log = LogFile::open(&path).unwrap();
+
+  assert_eq!(log.index.count(), 6);
+  assert_eq!(log.recovery().tail, Some(valid_len));
+  assert_eq!(log.recovery().quarantined, 4096);
+  assert_eq!(file_len(&path), valid_len);
+}
+
+#[test]
+fn undecodable_record_is_skipped() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = create(dir.path(), 2);
+  let len = file_len(&path);
+
+  // Valid frame and checksum around a payload that is no entry
+  let payload = [0xffu8; 3];
+  let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+  file
+    .write_all(&(payload.len() as u32).to_le_bytes())
+    .unwrap();
+  file
+    .write_all(&crc32fast::hash(&payload).to_le_bytes())
+    .unwrap();
+  file.write_all(&payload).unwrap();
+  drop(file);
+
+  {
+    let mut log = LogFile::open(&path).unwrap();
+    assert_eq!(log.index.count(), 2);
+    assert_eq!(log.recovery().damaged, vec![len]);
+    assert_eq!(log.recovery().tail, None);
+    log.add_entry(entry(2)).unwrap();
+  }
+
+  let log = LogFile::open(&path).unwrap();
+  assert_eq!(log.index.count(), 3);
+}
+
+#[test]
+fn reader_ignores_write_in_progress() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = create(dir.path(), 10);
+  append_torn_record(&path);
+
+  // A reader next to a live writer sees a partly flushed record
+  let log = LogFile::open_readonly(&path).unwrap();
+  let entries: Vec<_> = log.iter().unwrap().collect::<Result<_, _>>().unwrap();
+  assert_eq!(entries.len(), 10);
+  let entries: Vec<_> = log.iter_rev().unwrap().collect::<Result<_, _>>().unwrap();
+  assert_eq!(entries.len(), 10);
+  assert_eq!(log.tail(20).unwrap().filter(Result::is_err).count(), 0);
+}
+
+#[test]
+fn torn_tail_of_closed_file_is_corrupt() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = create(dir.path(), 10);
+  LogFile::open(&path).unwrap().close().unwrap();
+  append_torn_record(&path);
+
+  let log = LogFile::open_readonly(&path).unwrap();
+  assert_eq!(log.iter().unwrap().filter(Result::is_err).count(), 1);
+}
+
+#[test]
+fn closed_index_is_kept_without_sparse_index() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = create(dir.path(), 10);
+  let mut log = LogFile::open(&path).unwrap();
+  log.close().unwrap();
+  let index = log.index.clone();
+  drop(log);
+  std::fs::remove_file(dir.path().join("1.towl.sparse")).unwrap();
+  // Damage the last record, a rebuilt index would count one entry less
+  let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+  file.seek(SeekFrom::Start(file_len(&path) - 1)).unwrap();
+  file.write_all(&[0xff]).unwrap();
+  drop(file);
+
+  let log = LogFile::open_readonly(&path).unwrap();
+  assert_eq!(log.index.count(), index.count());
+  assert_eq!(log.index.closed(), index.closed());
+}
+
+#[test]
+fn oversized_entry_is_rejected() {
+  for compression in [Compression::None, Compression::Zstd] {
+    let dir = tempfile::tempdir().unwrap();
+    let path = common::path(dir.path());
+    let options = Options {
+      compression,
+      ..Options::default()
+    };
+    let mut log = init(dir.path(), options);
+    log.add_entry(entry(0)).unwrap();
+    let big = text_entry("x".repeat(17 * 1024 * 1024));
+    assert!(matches!(
+      log.add_entries(&[entry(1), big]),
+      Err(Error::EntryTooLarge { .. })
+    ));
+    log.add_entry(entry(2)).unwrap();
+    log.close().unwrap();
+    drop(log);
+
+    let log = LogFile::open(&path).unwrap();
+    assert!(log.recovery().is_clean());
+    assert_eq!(messages(&log), vec!["log entry 0", "log entry 2"]);
+  }
+}
+
+#[test]
+fn large_entry_closes_the_block() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = common::path(dir.path());
+  let options = Options {
+    compression: Compression::Zstd,
+    ..Options::default()
+  };
+  let mut log = init(dir.path(), options);
+  // Short of a full block, but no room for the large entry
+  let batch: Vec<Entry> = (0..9)
+    .map(|i| text_entry(format!("{i}").repeat(100 * 1024)))
+    .collect();
+  log.add_entries(&batch).unwrap();
+  let large = "y".repeat(15 * 1024 * 1024 + 512 * 1024);
+  log.add_entry(text_entry(large.clone())).unwrap();
+  log.add_entry(entry(10)).unwrap();
+  log.close().unwrap();
+  drop(log);
+
+  let log = LogFile::open(&path).unwrap();
+  assert!(log.recovery().is_clean());
+  assert_ne!(log.seek_to_count(8).unwrap(), log.seek_to_count(9).unwrap());
+  let messages = messages(&log);
+  assert_eq!(messages.len(), 11);
+  assert_eq!(messages[9], large);
+  assert_eq!(messages[10], "log entry 10");
+}
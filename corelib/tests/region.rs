@@ -0,0 +1,55 @@
+mod common;
+
+use common::{init, text_entry};
+use corelib::fs::{Entry, LogFile, Options};
+use corelib::Error;
+
+#[test]
+fn too_long_title_is_rejected() {
+  let dir = tempfile::tempdir().unwrap();
+  let res = LogFile::init(
+    dir.path().to_str().unwrap(),
+    "org".to_string(),
+    "t".repeat(2000),
+    1,
+  );
+
+  assert!(matches!(
+    res,
+    Err(Error::RegionOverflow {
+      region: "header",
+      ..
+    })
+  ));
+  // Nothing is left behind
+  assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+  let log = LogFile::init(
+    dir.path().to_str().unwrap(),
+    "org".to_string(),
+    "t".repeat(900),
+    1,
+  )
+  .unwrap();
+  drop(log);
+  let log = LogFile::open_readonly(common::path(dir.path())).unwrap();
+  assert_eq!(log.header.title.len(), 900);
+}
+
+#[test]
+fn index_fits_with_many_senders() {
+  let dir = tempfile::tempdir().unwrap();
+  let mut log = init(dir.path(), Options::default());
+  let batch: Vec<Entry> = (0..1000)
+    .map(|i| Entry {
+      sender: format!("{i:0>120}"),
+      ..text_entry(format!("log entry {i}"))
+    })
+    .collect();
+  log.add_entries(&batch).unwrap();
+  log.close().unwrap();
+  drop(log);
+
+  let log = LogFile::open(common::path(dir.path())).unwrap();
+  assert_eq!(log.index.count(), 1000);
+}
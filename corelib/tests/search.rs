@@ -0,0 +1,146 @@
+mod common;
+
+use common::{init, text_entry};
+use corelib::fs::{Cipher, Compression, Encryption, Entry, Key, KeyRing, LogFile, Options, Query};
+
+fn entry(i: usize) -> Entry {
+  let log_entry = match i % 100 {
+    0 => format!("GET /api/users request_id=req-{i:05} status=200"),
+    1 => format!("user {i} logged in from host alpha"),
+    2 => format!("alpha host user {i} logged out"),
+    _ => format!("noise line {i} lorem ipsum"),
+  };
+  text_entry(log_entry)
+}
+
+fn queries() -> Vec<Query> {
+  vec![
+    Query::terms("req-00300"),
+    Query::phrase("REQ 00300"),
+    Query::phrase("logged in"),
+    Query::terms("alpha logged"),
+    Query::phrase("host alpha"),
+    Query::phrase("in from host"),
+    Query::terms("nothing-here"),
+    Query::terms(""),
+  ]
+}
+
+fn search(log: &LogFile, query: &Query) -> Vec<String> {
+  log
+    .search(query.clone())
+    .unwrap()
+    .map(|entry| entry.unwrap().log_entry)
+    .collect()
+}
+
+fn expected(entries: &[Entry], query: &Query) -> Vec<String> {
+  entries
+    .iter()
+    .filter(|entry| query.matches(entry))
+    .map(|entry| entry.log_entry.clone())
+    .collect()
+}
+
+/// Same results with and without the sidecar, and for entries
+/// appended after it was built
+fn check(options: Options, keys: KeyRing) {
+  let dir = tempfile::tempdir().unwrap();
+  let path = common::path(dir.path());
+  let mut log = init(dir.path(), options);
+  let mut entries: Vec<Entry> = (0..5000).map(entry).collect();
+  for batch in entries.chunks(250) {
+    log.add_entries(batch).unwrap();
+  }
+  log.sync().unwrap();
+
+  // Without sidecar every entry is checked
+  for query in &queries() {
+    assert_eq!(search(&log, query), expected(&entries, query), "{query:?}");
+  }
+
+  log.close().unwrap();
+  assert!(dir.path().join("1.towl.idx").exists());
+  let extra: Vec<Entry> = (5000..5100).map(entry).collect();
+  log.add_entries(&extra).unwrap();
+  drop(log);
+  entries.extend(extra);
+
+  let log = LogFile::open_readonly_with(&path, &keys).unwrap();
+  for query in &queries() {
+    assert_eq!(search(&log, query), expected(&entries, query), "{query:?}");
+  }
+  assert_eq!(search(&log, &Query::terms("req-00300")).len(), 1);
+  assert_eq!(search(&log, &Query::phrase("logged in")).len(), 51);
+}
+
+#[test]
+fn term_and_phrase_search() {
+  check(Options::default(), KeyRing::default());
+}
+
+#[test]
+fn compressed_search() {
+  let options = Options {
+    compression: Compression::Zstd,
+    ..Options::default()
+  };
+  check(options, KeyRing::default());
+}
+
+#[test]
+fn encrypted_search() {
+  let key = Key::new("k1".to_string(), [7; 32]);
+  let options = Options {
+    encryption: Some(Encryption {
+      cipher: Cipher::Aes256Gcm,
+      key: key.clone(),
+    }),
+    ..Options::default()
+  };
+  check(options, KeyRing::from(key));
+
+  // The sidecar is sealed with the file key
+  let dir = tempfile::tempdir().unwrap();
+  let mut log = init(
+    dir.path(),
+    Options {
+      encryption: Some(Encryption {
+        cipher: Cipher::Aes256Gcm,
+        key: Key::new("k1".to_string(), [7; 32]),
+      }),
+      ..Options::default()
+    },
+  );
+  log.add_entries(&[entry(1)]).unwrap();
+  log.close().unwrap();
+  let raw = std::fs::read(dir.path().join("1.towl.idx")).unwrap();
+  assert!(!raw.windows(5).any(|w| w == b"alpha"));
+}
+
+#[test]
+fn sidecar_skips_other_records() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = common::path(dir.path());
+  let mut log = init(dir.path(), Options::default());
+  let entries: Vec<Entry> = (0..5000).map(entry).collect();
+  log.add_entries(&entries).unwrap();
+  log.close().unwrap();
+  let offset = log.seek_to_count(4050).unwrap() as usize;
+  drop(log);
+
+  // Damage a record without the searched term
+  let mut data = std::fs::read(&path).unwrap();
+  data[offset + 12] ^= 0xff;
+  std::fs::write(&path, data).unwrap();
+
+  let log = LogFile::open_readonly(&path).unwrap();
+  let found: Vec<_> = log.search(Query::terms("req-00300")).unwrap().collect();
+  assert_eq!(found.len(), 1);
+  assert!(found[0].is_ok());
+
+  // A full scan runs into the damaged record
+  std::fs::remove_file(dir.path().join("1.towl.idx")).unwrap();
+  let found: Vec<_> = log.search(Query::terms("req-00300")).unwrap().collect();
+  assert!(found.iter().any(Result::is_err));
+}
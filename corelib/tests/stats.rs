@@ -0,0 +1,83 @@
+mod common;
+
+use common::{init, text_entry};
+use corelib::fs::{Compression, Entry, Fields, LogFile, Options, Severity};
+
+/// Entry of one of 300 senders and 3 log formats,
+/// two in three with a severity
+fn entry(i: usize) -> Entry {
+  let severity = [None, Some(Severity::Error), Some(Severity::Info)][i % 3];
+  Entry {
+    sender: format!("host{}", i % 300),
+    log_format: (i % 3) as i32,
+    fields: severity.map(|severity| Fields {
+      severity: Some(severity),
+      ..Fields::default()
+    }),
+    ..text_entry("x".repeat(i % 50))
+  }
+}
+
+#[test]
+fn counts_by_sender_format_and_severity() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = common::path(dir.path());
+  let options = Options {
+    compression: Compression::Zstd,
+    ..Options::default()
+  };
+  let mut log = init(dir.path(), options);
+  let entries: Vec<Entry> = (0..3000).map(entry).collect();
+  for batch in entries.chunks(500) {
+    log.add_entries(batch).unwrap();
+  }
+  log.sync().unwrap();
+
+  let stats = log.index.stats().clone();
+  // Only the first senders are counted one by one
+  assert_eq!(stats.senders.len(), 128);
+  assert_eq!(stats.senders["host0"], 10);
+  assert_eq!(stats.other_senders, 3000 - 128 * 10);
+  assert_eq!(stats.log_formats.len(), 3);
+  assert_eq!(stats.log_formats[&0], 1000);
+  assert_eq!(stats.other_log_formats, 0);
+  assert_eq!(stats.severities[&Severity::Error], 1000);
+  assert_eq!(stats.severities[&Severity::Info], 1000);
+  assert_eq!(stats.severities.len(), 2);
+  let sizes: Vec<u64> = entries
+    .iter()
+    .map(|entry| bincode::serialized_size(entry).unwrap())
+    .collect();
+  assert_eq!(stats.min_entry_size, sizes.iter().min().copied());
+  assert_eq!(stats.max_entry_size, sizes.iter().max().copied());
+  // Compressed records are smaller than the file
+  assert!(stats.bytes > 0 && stats.bytes < std::fs::metadata(&path).unwrap().len());
+
+  // Saved with the index and rebuilt the same way
+  log.close().unwrap();
+  drop(log);
+  let log = LogFile::open_readonly(&path).unwrap();
+  assert_eq!(log.index.stats(), &stats);
+  let mut log = LogFile::open(&path).unwrap();
+  log.reindex().unwrap();
+  assert_eq!(log.index.stats(), &stats);
+}
+
+#[test]
+fn many_log_formats() {
+  let dir = tempfile::tempdir().unwrap();
+  let mut log = init(dir.path(), Options::default());
+  let entries: Vec<Entry> = (0..100)
+    .map(|i| Entry {
+      log_format: i,
+      ..text_entry(format!("log entry {i}"))
+    })
+    .collect();
+  log.add_entries(&entries).unwrap();
+
+  let stats = log.index.stats();
+  assert_eq!(stats.log_formats.len(), 64);
+  assert_eq!(stats.other_log_formats, 36);
+  assert_eq!(stats.senders["test"], 100);
+  assert!(stats.severities.is_empty());
+}
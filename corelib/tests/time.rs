@@ -0,0 +1,102 @@
+mod common;
+
+use chrono::{TimeZone, Utc};
+use common::{init, text_entry};
+use corelib::fs::{Clock, Entry, Fields, LogFile, Options, TimeRange};
+
+/// Entry `i` received at `received`, sent at `source`
+fn entry(i: usize, received: i64, source: i64) -> Entry {
+  Entry {
+    received: Utc.timestamp_opt(received, 0).unwrap(),
+    fields: Some(Fields {
+      timestamp: Utc.timestamp_opt(source, 0).single(),
+      ..Fields::default()
+    }),
+    ..text_entry(format!("log entry {i}"))
+  }
+}
+
+fn found(log: &LogFile, range: TimeRange) -> Vec<String> {
+  log
+    .iter_range(range)
+    .unwrap()
+    .map(|entry| entry.unwrap().log_entry)
+    .collect()
+}
+
+fn expected(entries: &[Entry], range: &TimeRange) -> Vec<String> {
+  entries
+    .iter()
+    .filter(|entry| range.contains(entry))
+    .map(|entry| entry.log_entry.clone())
+    .collect()
+}
+
+/// Ranges over times 0..5500, many across several segments
+fn ranges(clock: Clock) -> Vec<TimeRange> {
+  let at = |t| Utc.timestamp_opt(t, 0).unwrap();
+  let mut res = vec![TimeRange::new(clock)];
+  for after in [0, 999, 1000, 1023, 1024, 2500, 3999, 4000] {
+    res.push(TimeRange::new(clock).after(at(after)));
+    res.push(TimeRange::new(clock).before(at(after)));
+    res.push(
+      TimeRange::new(clock)
+        .after(at(after))
+        .before(at(after + 1500)),
+    );
+  }
+  res
+}
+
+/// Check every range on the written file and after reopening it
+fn check(entries: &[Entry], clock: Clock) {
+  let dir = tempfile::tempdir().unwrap();
+  let mut log = init(dir.path(), Options::default());
+  for entry in entries {
+    log.add_entry(entry.clone()).unwrap();
+  }
+  for range in ranges(clock) {
+    assert_eq!(found(&log, range), expected(entries, &range), "{range:?}");
+  }
+
+  log.close().unwrap();
+  drop(log);
+  let log = LogFile::open_readonly(common::path(dir.path())).unwrap();
+  for range in ranges(clock) {
+    assert_eq!(found(&log, range), expected(entries, &range), "{range:?}");
+  }
+}
+
+#[test]
+fn received_in_order() {
+  let entries: Vec<Entry> = (0..3000).map(|i| entry(i, 1000 + i as i64, 0)).collect();
+  check(&entries, Clock::Received);
+}
+
+#[test]
+fn clock_going_backwards() {
+  // The last segment was received before all others, so the
+  // sparse index cannot binary search
+  let entries: Vec<Entry> = (0..3000)
+    .map(|i| {
+      let t = if i < 2048 { 1000 + i } else { i - 2000 };
+      entry(i, t as i64, 0)
+    })
+    .collect();
+  check(&entries, Clock::Received);
+}
+
+#[test]
+fn source_time_ranges() {
+  // Source times of different hosts are not ordered
+  let entries: Vec<Entry> = (0..3000)
+    .map(|i| entry(i, 1000 + i as i64, ((i * 7919) % 3000) as i64 + 1000))
+    .collect();
+  check(&entries, Clock::Source);
+
+  // Sources in a narrow window per segment, so most are skipped
+  let entries: Vec<Entry> = (0..3000)
+    .map(|i| entry(i, 1000 + i as i64, 4000 - i as i64))
+    .collect();
+  check(&entries, Clock::Source);
+}
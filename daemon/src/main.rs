@@ -1,4 +1,5 @@
 use chrono::Utc;
+use corelib::format::LogFormat;
 use proto::towl::Entry;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
@@ -96,7 +97,7 @@ async fn sender(mut rx: Receiver<String>, config: Config) {
     let received_rfc3339 = Utc::now().to_rfc3339();
 
     // Log format Journalctl JSON
-    let log_format = 1;
+    let log_format = LogFormat::Journald.code();
 
     let _ = remote
       .add(Entry {
@@ -104,6 +105,9 @@ async fn sender(mut rx: Receiver<String>, config: Config) {
         received_rfc3339,
         log_format,
         log_entry,
+        fields: None,
+        // Assigned by the server
+        seq: 0,
       })
       .await
       .expect("Error adding log entry to remote");
@@ -8,8 +8,43 @@ pub struct Entry {
     pub received_rfc3339: ::prost::alloc::string::String,
     #[prost(int32, tag = "3")]
     pub log_format: i32,
+    /// Raw log text
     #[prost(string, tag = "4")]
     pub log_entry: ::prost::alloc::string::String,
+    /// Structured fields, unset if the entry was not parsed
+    #[prost(message, optional, tag = "5")]
+    pub fields: ::core::option::Option<Fields>,
+    /// Global sequence number assigned by the server, 0 if unknown
+    #[prost(uint64, tag = "6")]
+    pub seq: u64,
+}
+/// Structured fields of an entry
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Fields {
+    /// Syslog severity code, 0 (emergency) - 7 (debug)
+    #[prost(int32, optional, tag = "1")]
+    pub severity: ::core::option::Option<i32>,
+    #[prost(string, optional, tag = "2")]
+    pub hostname: ::core::option::Option<::prost::alloc::string::String>,
+    /// Systemd unit or application name
+    #[prost(string, optional, tag = "3")]
+    pub unit: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(uint32, optional, tag = "4")]
+    pub pid: ::core::option::Option<u32>,
+    /// Log message without metadata
+    #[prost(string, optional, tag = "5")]
+    pub message: ::core::option::Option<::prost::alloc::string::String>,
+    /// Other key/value pairs
+    #[prost(map = "string, string", tag = "6")]
+    pub extra: ::std::collections::HashMap<
+        ::prost::alloc::string::String,
+        ::prost::alloc::string::String,
+>,
+    /// Event time at the source, e.g. journald __REALTIME_TIMESTAMP
+    #[prost(string, optional, tag = "7")]
+    pub timestamp_rfc3339: ::core::option::Option<::prost::alloc::string::String>,
 }
 #[derive(serde::Serialize, serde::Deserialize)]
 #[allow(clippy::derive_partial_eq_without_eq)]
@@ -36,6 +71,45 @@ pub struct GetRequest {
     pub after_counter: ::prost::alloc::string::String,
     #[prost(bool, tag = "3")]
     pub follow: bool,
+    /// Resume after the entry with this sequence number, 0 to ignore
+    #[prost(uint64, tag = "4")]
+    pub after_seq: u64,
+    /// Only entries matching this filter, ordered by time
+    #[prost(message, optional, tag = "5")]
+    pub filter: ::core::option::Option<Filter>,
+}
+/// Conditions of returned entries, unset ones match every entry
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Filter {
+    /// Time range, both ends exclusive, empty for unbounded
+    #[prost(string, tag = "1")]
+    pub after_rfc3339: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub before_rfc3339: ::prost::alloc::string::String,
+    /// Use the source time instead of the received time
+    #[prost(bool, tag = "3")]
+    pub source_time: bool,
+    #[prost(string, repeated, tag = "4")]
+    pub senders: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    #[prost(int32, repeated, tag = "5")]
+    pub log_formats: ::prost::alloc::vec::Vec<i32>,
+    /// Only entries at least this severe, 0 (emergency) - 7 (debug)
+    #[prost(int32, optional, tag = "6")]
+    pub min_severity: ::core::option::Option<i32>,
+    /// Substring of the raw log text
+    #[prost(string, tag = "7")]
+    pub contains: ::prost::alloc::string::String,
+    /// Regular expression on the raw log text
+    #[prost(string, tag = "8")]
+    pub regex: ::prost::alloc::string::String,
+    /// Structured field values by field name
+    #[prost(map = "string, string", tag = "9")]
+    pub fields: ::std::collections::HashMap<
+        ::prost::alloc::string::String,
+        ::prost::alloc::string::String,
+    >,
 }
 #[derive(serde::Serialize, serde::Deserialize)]
 #[allow(clippy::derive_partial_eq_without_eq)]
@@ -1,55 +1,111 @@
-use corelib::fs::LogFile;
+use chrono::{DateTime, Utc};
+use corelib::fs::{Clock, Entry, Filter, LogFile, Severity, TimeRange};
+use corelib::logger::{Logger, DATA_PATH};
+use corelib::store::LogStore;
 use proto::towl::towl_server::Towl;
 use std::{
   convert::Infallible,
   net::{SocketAddr, ToSocketAddrs},
-  sync::Arc,
+  path::PathBuf,
 };
-use tokio::sync::Mutex;
+use tokio::sync::{broadcast::error::RecvError, mpsc::Sender};
 use tokio_stream::wrappers::ReceiverStream;
 use tonic::{transport::Server, Response, Status};
 
+/// Entries kept in the working file before it is archived
+const FILE_MAX_COUNT: i32 = 100_000;
+
 #[derive(Clone)]
 struct Context {
-  logger: Arc<Mutex<LogFile>>,
+  logger: Logger,
 }
 
 impl Context {
-  async fn init() -> Result<Self, String> {
-    let config = Config::builder()
-      .org("gz".into())
-      .title("log".into())
-      .archive_strategy(core::logger::Archive::Daily);
-    let logger = Logger::init(config).await?;
+  async fn init() -> Result<Self, Status> {
+    let logger = Logger::init("gz".into(), "log".into(), FILE_MAX_COUNT).await?;
     Ok(Self { logger })
   }
 }
 
 #[tonic::async_trait]
 impl TowlServer for Context {
-  type GetLogsStream = ReceiverStream<Result<proto::towl::Entry, Status>>;
-  async fn get_logs(
+  type GetStream = ReceiverStream<Result<proto::towl::Entry, Status>>;
+  async fn get(
     &self,
-    request: tonic::Request<proto::towl::LogRequest>,
-  ) -> Result<tonic::Response<Self::GetLogsStream>, tonic::Status> {
-    // Create channel for stream response
-    let (mut tx, rx) = tokio::sync::mpsc::channel(100);
+    request: tonic::Request<proto::towl::GetRequest>,
+  ) -> Result<tonic::Response<Self::GetStream>, tonic::Status> {
+    let request = request.into_inner();
+    let filter = filter(&request)?;
+    let file_id = parse_number(&request.file_id, "file id")?;
+    let after_counter = parse_number(&request.after_counter, "counter")?;
 
-    let db = self.db.clone();
-    let logs_after = request.into_inner().logs_after;
+    // Read-only catalog, the logger keeps writing the working file
+    let store = LogStore::open_readonly(DATA_PATH)?;
+    let read = match file_id {
+      Some(id) => {
+        // Only the working file gets new entries
+        if request.follow && store.working().map(|info| info.id) != Some(id) {
+          return Err(Status::invalid_argument(format!(
+            "File {id} is not the working file, it cannot be followed"
+          )));
+        }
+        Read::File {
+          path: store.path(id).ok_or(corelib::Error::UnknownId(id))?,
+          after_counter: after_counter.unwrap_or(0),
+        }
+      }
+      None if after_counter.is_some() => {
+        return Err(Status::invalid_argument("A counter needs a file id"));
+      }
+      None => Read::Store(store),
+    };
+
+    // Subscribe before reading, so no entry added meanwhile is missed
+    let mut watch = request.follow.then(|| self.logger.watch());
+
+    // Create channel for stream response
+    let (tx, rx) = tokio::sync::mpsc::channel(100);
 
-    // Send the result items through the channel
     tokio::spawn(async move {
-      let r = db.lock().await.clone();
-      for item in r.into_iter().filter(|i| {
-        if let Some(c) = i.created {
-          c.timestamp() > logs_after
-        } else {
-          false
+      // The files are read and merged on a blocking thread
+      let stored = {
+        let tx = tx.clone();
+        let filter = filter.clone();
+        tokio::task::spawn_blocking(move || read.send(filter, &tx))
+          .await
+          .expect("Error during spawn blocking when reading entries")
+      };
+      let mut last_seq = match stored {
+        Ok(last_seq) => last_seq,
+        Err(status) => {
+          let _ = tx.send(Err(status)).await;
+          return;
+        }
+      };
+
+      // New entries, skipping the ones read from the files already
+      let Some(watch) = watch.as_mut() else {
+        return;
+      };
+      loop {
+        match watch.recv().await {
+          Ok(entry) if entry.seq > last_seq && filter.matches(&entry) => {
+            last_seq = entry.seq;
+            if tx.send(Ok(entry.into())).await.is_err() {
+              // Client is gone
+              break;
+            }
+          }
+          Ok(_) => (),
+          Err(RecvError::Lagged(n)) => {
+            let status = Status::data_loss(format!(
+              "Missed {n} new entries, resume with after_seq {last_seq}"
+            ));
+            let _ = tx.send(Err(status)).await;
+            break;
+          }
+          Err(RecvError::Closed) => break,
         }
-      }) {
-        let res: proto::towl::Entry = item.into();
-        tx.send(Ok(res)).await.unwrap();
       }
     });
 
@@ -58,6 +114,114 @@ impl TowlServer for Context {
   }
 }
 
+/// Stored entries a Get request reads
+enum Read {
+  /// Entries of one file from the given count on
+  File { path: PathBuf, after_counter: usize },
+  /// Entries of every file, merged by time
+  Store(LogStore),
+}
+
+impl Read {
+  /// Send the matching entries, returns the highest sequence
+  /// number sent
+  fn send(
+    self,
+    filter: Filter,
+    tx: &Sender<Result<proto::towl::Entry, Status>>,
+  ) -> Result<u64, Status> {
+    let entries: Box<dyn Iterator<Item = corelib::Result<Entry>>> = match self {
+      // The sparse index finds the first entry and skips the
+      // segments that cannot match, like a query over the store
+      Read::File {
+        path,
+        after_counter,
+      } => {
+        let log = LogFile::open_readonly(path)?;
+        Box::new(log.iter_filter_from(after_counter, filter)?)
+      }
+      Read::Store(store) => Box::new(store.query(filter)),
+    };
+
+    let mut last_seq = 0;
+    for entry in entries {
+      let res = entry.map_err(Status::from).map(|entry| {
+        last_seq = last_seq.max(entry.seq);
+        entry.into()
+      });
+      if tx.blocking_send(res).is_err() {
+        // Client is gone
+        break;
+      }
+    }
+    Ok(last_seq)
+  }
+}
+
+/// Optional number field of a request, empty if unset
+fn parse_number(s: &str, name: &str) -> Result<Option<usize>, Status> {
+  if s.is_empty() {
+    return Ok(None);
+  }
+  s.parse()
+    .map(Some)
+    .map_err(|e| Status::invalid_argument(format!("Invalid {name} {s}: {e}")))
+}
+
+/// Entry filter of a Get request
+fn filter(request: &proto::towl::GetRequest) -> Result<Filter, Status> {
+  let mut res = Filter::new();
+  if request.after_seq != 0 {
+    res = res.after_seq(request.after_seq);
+  }
+  let f = match &request.filter {
+    Some(f) => f,
+    None => return Ok(res),
+  };
+  let clock = if f.source_time {
+    Clock::Source
+  } else {
+    Clock::Received
+  };
+  let mut range = TimeRange::new(clock);
+  if !f.after_rfc3339.is_empty() {
+    range = range.after(parse_time(&f.after_rfc3339)?);
+  }
+  if !f.before_rfc3339.is_empty() {
+    range = range.before(parse_time(&f.before_rfc3339)?);
+  }
+  res = res.range(range);
+  for sender in &f.senders {
+    res = res.sender(sender);
+  }
+  for log_format in &f.log_formats {
+    res = res.log_format(*log_format);
+  }
+  if let Some(code) = f.min_severity {
+    let severity = Severity::from_code(code.into())
+      .ok_or_else(|| Status::invalid_argument(format!("Unknown severity code {code}")))?;
+    res = res.min_severity(severity);
+  }
+  if !f.contains.is_empty() {
+    res = res.contains(&f.contains);
+  }
+  if !f.regex.is_empty() {
+    res = res
+      .regex(&f.regex)
+      .map_err(|e| Status::invalid_argument(e.to_string()))?;
+  }
+  for (name, value) in &f.fields {
+    res = res.field(name, value);
+  }
+  Ok(res)
+}
+
+fn parse_time(s: &str) -> Result<DateTime<Utc>, Status> {
+  DateTime::parse_from_rfc3339(s)
+    .map(|dt| dt.with_timezone(&Utc))
+    .map_err(|e| Status::invalid_argument(format!("Invalid time {s}: {e}")))
+}
+
 async fn set_log(
   ip: Option<SocketAddr>,
   ctx: Context,